@@ -0,0 +1,172 @@
+use std::path::{Path, PathBuf};
+
+use clap::{CommandFactory, Parser, Subcommand};
+
+use crate::commands::{in_commands::InSubCommands, out_commands::OutSubCommands};
+
+/////////////////////////////////////////////////////////////////////
+#[derive(Parser, Debug)]
+#[clap(
+    author = "Terroid",
+    version = "0.0.1",
+    about = "A Loose structured and easy to use python project manager.",
+    long_about = "A Loose structured and easy to use python project manager. In-project commands work on the project found in the current directory or one of its parents, or on the one selected with -C or --project."
+    )
+]
+pub struct Cli {
+    /// Runs as if pie was started in <DIR>.
+    #[clap(short('C'), value_name("DIR"), long_help("Runs as if pie was started in <DIR>: commands like 'new', 'list' and 'exec' work from it, and in-project commands on the project enclosing it."))]
+    pub dir: Option<PathBuf>,
+
+    /// Runs in-project commands on <PROJECT>, a project folder or the name of a project.
+    #[clap(long, value_name("PROJECT"), long_help("Runs in-project commands on <PROJECT>: a project folder, or the name of a project, by folder or by its name in project.json, in the current directory or next to the project enclosing it. Unlike -C, it doesn't change the directory pie runs in, which commands like 'new', 'list' and 'exec' work from."))]
+    pub project: Option<PathBuf>,
+
+    /// Fails instead of creating the venv when a command needs it and it is missing.
+    #[clap(long)]
+    pub no_auto_venv: bool,
+
+    #[clap(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+
+    #[clap(flatten)]
+    In(InSubCommands),
+
+    #[clap(flatten)]
+    Out(OutSubCommands),
+
+    /// Lists all the pie projects, or inside a project, runs `pip list` inside the venv.
+    List{
+        #[clap(allow_hyphen_values = true)]
+        args: Vec<String>
+    },
+
+    /// Runs a pie project folder or a python file.
+    #[clap(
+        trailing_var_arg = true,
+        allow_hyphen_values = true,
+        disable_help_flag = true,
+        long_about("Runs a pie project folder or a python file. Everything after the path, including --help, is passed on to python; use 'pie help exec' for this help. 'pie <folder|file.py> [args]' is a shorthand for it, as long as the name isn't also a subcommand.")
+    )]
+    Exec{
+        /// The project folder or python file to run.
+        path: String,
+
+        /// Arguments passed on to the python process.
+        #[clap(allow_hyphen_values = true)]
+        args: Vec<String>
+    },
+
+    /// Validates project.json.
+    #[clap(long_about("Validates project.json: required fields, field types, that entry_point exists under working_directory, that version is valid SemVer, that license is an SPDX expression and that version_files exist. Reports every problem with its line and column."))]
+    Check
+
+}
+
+/// Rewrites the `pie <folder|file.py> [args]` shorthand to `pie exec <folder|file.py> [args]`.
+/// A subcommand always wins over a file or folder of the same name, and the returned warning says so.
+pub fn expand_shorthand<F>(mut args: Vec<String>, exists: F) -> (Vec<String>, Option<String>)
+where
+    F: Fn(&Path) -> bool
+{
+    let first = match args.get(1) {
+        Some(first) if !first.starts_with('-') => first,
+        _ => return (args, None),
+    };
+
+    if is_subcommand(first) {
+        let warning = exists(Path::new(first)).then(|| format!("'{0}' is both a subcommand and a file or folder here, running the subcommand. Use 'pie exec {0}' to run the file or folder.", first));
+        return (args, warning);
+    }
+
+    if exists(Path::new(first)) || first.ends_with(".py") {
+        args.insert(1, String::from("exec"));
+    }
+    (args, None)
+}
+
+fn is_subcommand(name: &str) -> bool {
+    name == "help" || Cli::command().get_subcommands().any(|command| command.get_name() == name || command.get_all_aliases().any(|alias| alias == name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::{in_commands::InSubCommands, out_commands::OutSubCommands};
+
+    fn argv(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn expand(args: &[&str], existing: &[&str]) -> (Vec<String>, Option<String>) {
+        expand_shorthand(argv(args), |path| existing.iter().any(|existing| Path::new(existing) == path))
+    }
+
+    #[test]
+    fn folder_runs_through_exec() {
+        let (args, warning) = expand(&["pie", "demo", "-v", "x"], &["demo"]);
+        assert_eq!(args, argv(&["pie", "exec", "demo", "-v", "x"]));
+        assert_eq!(warning, None);
+    }
+
+    #[test]
+    fn python_file_runs_through_exec_even_if_missing() {
+        assert_eq!(expand(&["pie", "main.py"], &["main.py"]).0, argv(&["pie", "exec", "main.py"]));
+        assert_eq!(expand(&["pie", "gone.py"], &[]).0, argv(&["pie", "exec", "gone.py"]));
+    }
+
+    #[test]
+    fn subcommand_wins_over_folder_of_the_same_name() {
+        for name in ["list", "new", "cfg", "run", "help"] {
+            let (args, warning) = expand(&["pie", name], &[name]);
+            assert_eq!(args, argv(&["pie", name]));
+            assert!(warning.unwrap().contains(&format!("pie exec {}", name)));
+        }
+    }
+
+    #[test]
+    fn subcommand_without_a_clash_has_no_warning() {
+        assert_eq!(expand(&["pie", "list"], &["demo"]), (argv(&["pie", "list"]), None));
+    }
+
+    #[test]
+    fn flags_and_unknown_names_are_left_to_clap() {
+        assert_eq!(expand(&["pie", "-C", "demo", "run"], &["demo"]).0, argv(&["pie", "-C", "demo", "run"]));
+        assert_eq!(expand(&["pie", "nothing"], &[]).0, argv(&["pie", "nothing"]));
+        assert_eq!(expand(&["pie"], &[]).0, argv(&["pie"]));
+    }
+
+    #[test]
+    fn explicit_exec_is_not_expanded_again() {
+        assert_eq!(expand(&["pie", "exec", "list"], &["list"]), (argv(&["pie", "exec", "list"]), None));
+    }
+
+    #[test]
+    fn exec_parses_a_subcommand_name_as_a_path() {
+        match Cli::try_parse_from(["pie", "exec", "list", "--flag", "a"]).unwrap().command {
+            Command::Exec { path, args } => {
+                assert_eq!(path, "list");
+                assert_eq!(args, argv(&["--flag", "a"]));
+            },
+            command => panic!("parsed as {:?}", command),
+        }
+    }
+
+    #[test]
+    fn in_and_out_commands_share_one_parser() {
+        let cli = Cli::try_parse_from(["pie", "-C", "demo", "install", "requests"]).unwrap();
+        assert_eq!(cli.dir, Some(PathBuf::from("demo")));
+        assert!(matches!(cli.command, Command::In(InSubCommands::Install { .. })));
+
+        let cli = Cli::try_parse_from(["pie", "--project", "demo", "new", "x", "y"]).unwrap();
+        assert_eq!(cli.project, Some(PathBuf::from("demo")));
+        assert!(matches!(cli.command, Command::Out(OutSubCommands::New { .. })));
+
+        assert!(matches!(Cli::try_parse_from(["pie", "list"]).unwrap().command, Command::List { .. }));
+        assert!(matches!(Cli::try_parse_from(["pie", "check"]).unwrap().command, Command::Check));
+    }
+}
@@ -0,0 +1,83 @@
+use std::path::PathBuf;
+
+use ansi_term::Color;
+use pie::{PieError, Result};
+use serde::Serialize;
+
+use crate::{config::MainConfig, project::Project, python::{dists, lock::Lock, osv::{self, Level}, pep440::Version}};
+
+use super::in_commands::ensure_venv;
+
+#[derive(Serialize)]
+struct Finding {
+    name: String,
+    version: String,
+    id: String,
+    aliases: Vec<String>,
+    /// None when the advisory gives no severity.
+    severity: Option<Level>,
+    fixed: Vec<String>,
+    summary: String,
+}
+
+/// Checks every package in the venv, pip and setuptools included, or pie.lock's with `lock`, against an OSV advisory database on disk:
+/// `db`, or else the `advisory_db` of pie's config. Fails if an advisory is at least `fail_on` severe.
+pub fn audit(db: Option<PathBuf>, lock: bool, fail_on: Level, json: bool, project: &Project) -> Result<()> {
+    let db = match db.or_else(|| MainConfig::from_file().ok().and_then(|config| config.advisory_db)) {
+        Some(db) => db,
+        None => return Err(PieError::Config(String::from("No advisory database. Set 'advisory_db' in pie's config.json to an OSV folder or zip, or pass --db."))),
+    };
+
+    let packages = if lock {
+        Lock::read(&project.lock_file())?.packages.into_iter().map(|package| (package.name, package.version)).collect::<Vec<_>>()
+    } else {
+        ensure_venv(project)?;
+        dists::installed(&project.venv()).into_iter().map(|dist| (dist.name, dist.version)).collect()
+    };
+
+    let advisories = osv::load(&db)?;
+    let mut findings = vec![];
+    for (name, version) in &packages {
+        let parsed = match version.parse::<Version>() {
+            Ok(parsed) => parsed,
+            Err(_) => continue,
+        };
+        for advisory in &advisories {
+            if let Some(fixed) = advisory.affects(name, &parsed) {
+                findings.push(Finding {
+                    name: name.to_owned(),
+                    version: version.to_owned(),
+                    id: advisory.id.to_owned(),
+                    aliases: advisory.aliases.clone(),
+                    severity: advisory.level(),
+                    fixed,
+                    summary: advisory.summary.to_owned(),
+                });
+            }
+        }
+    }
+    let failing = findings.iter().filter(|finding| finding.severity.unwrap_or(Level::Low) >= fail_on).count();
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&findings).map_err(|e| PieError::Failed(e.to_string()))?);
+    } else if findings.is_empty() {
+        println!("{}", Color::Green.paint(format!("√ |> No known vulnerabilities in {} packages ({} advisories checked).", packages.len(), advisories.len())));
+    } else {
+        for finding in &findings {
+            let severity = finding.severity.map_or_else(|| String::from("unknown"), |level| level.to_string());
+            let fixed = if finding.fixed.is_empty() { String::from("no fix yet") } else { format!("fixed in {}", finding.fixed.join(", ")) };
+            let aliases = if finding.aliases.is_empty() { String::new() } else { format!(" ({})", finding.aliases.join(", ")) };
+
+            let color = if finding.severity.unwrap_or(Level::Low) >= fail_on { Color::Red } else { Color::Yellow };
+            println!("{}", color.paint(format!("{} {}: {}{} [{}], {}", finding.name, finding.version, finding.id, aliases, severity, fixed)));
+            if !finding.summary.is_empty() {
+                println!("    {}", finding.summary);
+            }
+        }
+    }
+
+    if failing > 0 {
+        return Err(PieError::Failed(format!("{} vulnerabilit{} of {} severity or worse found.", failing, if failing == 1 {"y"} else {"ies"}, fail_on)));
+    }
+    Ok(())
+}
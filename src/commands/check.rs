@@ -0,0 +1,78 @@
+use std::{path::Path, str::FromStr};
+
+use ansi_term::Color;
+use pie::{PieError, Result};
+use serde_json::Value;
+
+use crate::{config::{describe_problem, ProjectFile}, semver::Version};
+
+const REQUIRED: [&str; 2] = ["entry_point", "working_directory"];
+
+/// Validates a project.json, printing every problem found instead of stopping at the first one.
+pub fn check(path: &Path) -> Result<()> {
+
+    let file = ProjectFile::open(path)?;
+    let root = path.parent().unwrap_or_else(|| Path::new("."));
+    let mut problems = vec![];
+
+    let problem = |key: &str, msg: String| describe_problem(file.position(key), Some(key), &msg);
+
+    let missing = REQUIRED.iter().filter(|key| file.get(key).is_none()).collect::<Vec<_>>();
+    for key in &missing {
+        problems.push(format!("missing required field '{}'", key));
+    }
+    // With a required field missing serde would only repeat that, the other checks still run.
+    if missing.is_empty() {
+        if let Err(PieError::InvalidProject { reason, .. }) = file.config() {
+            problems.push(reason);
+        }
+    }
+
+    if let (Some(Value::String(working_directory)), Some(Value::String(entry_point))) = (file.get("working_directory"), file.get("entry_point")) {
+        let working_directory = root.join(working_directory);
+        if !working_directory.is_dir() {
+            problems.push(problem("working_directory", format!("'{}' is not a directory", working_directory.display())));
+        } else if !working_directory.join(entry_point).is_file() {
+            problems.push(problem("entry_point", format!("'{}' does not exist", working_directory.join(entry_point).display())));
+        }
+    }
+
+    if let Some(Value::String(version)) = file.get("version") {
+        if let Err(e) = Version::from_str(version) {
+            problems.push(problem("version", format!("'{}' is not valid SemVer: {}", version, e)));
+        }
+    }
+
+    if let Some(Value::String(license)) = file.get("license") {
+        if let Err(e) = spdx::Expression::parse(license) {
+            let term = license.get(e.span.clone()).unwrap_or_default();
+            problems.push(problem("license", format!("'{}' is not an SPDX license expression: {} '{}'", license, e.reason, term)));
+        }
+    }
+
+    if let Some(Value::Array(version_files)) = file.get("version_files") {
+        for entry in version_files {
+            let version_file = match entry {
+                Value::String(version_file) => version_file,
+                Value::Object(entry) => match entry.get("path") {
+                    Some(Value::String(version_file)) => version_file,
+                    _ => continue,
+                },
+                _ => continue,
+            };
+            if !root.join(version_file).is_file() {
+                problems.push(problem("version_files", format!("'{}' does not exist", version_file)));
+            }
+        }
+    }
+
+    if problems.is_empty() {
+        println!("{}", Color::Green.paint(format!("√ |> '{}' is valid.", path.display())));
+        return Ok(());
+    }
+
+    for problem in &problems {
+        println!("{}", Color::Red.paint(format!("X |> {}", problem)));
+    }
+    Err(PieError::InvalidProject { path: path.to_owned(), reason: format!("{} problem(s) found.", problems.len()) })
+}
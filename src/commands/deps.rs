@@ -0,0 +1,59 @@
+use std::{collections::{BTreeMap, BTreeSet}, fs};
+
+use ansi_term::Color;
+use clap::Subcommand;
+use pie::{PieError, Result};
+
+use crate::{project::Project, python::{dists::{self, closure, normalize}, lock::is_locked, requirements}};
+
+use super::in_commands::{grouped_distributions, imported_distributions};
+
+#[derive(Debug, Subcommand)]
+pub enum DepsSubCommands {
+    /// Finds dependencies that are unused, undeclared, or installed for nothing.
+    #[clap(long_about("Compares the project's imports, requirements.txt, the dependency groups in project.json and the venv. Reports requirements that are never imported, imports that aren't declared, and packages installed in the venv that are neither declared nor imported, nor needed by one that is. Exits with a non-zero code if anything is found, for use in CI."))]
+    Check,
+}
+
+/// Reports the drift between what the project imports, what it declares and what is installed.
+pub fn check(project: &Project) -> Result<()> {
+    let requirements_txt = fs::read_to_string(project.requirements()).unwrap_or_default();
+    let declared = requirements_txt.lines().filter_map(requirements::requirement_name).collect::<Vec<String>>();
+    let grouped = grouped_distributions(project);
+    let imported = imported_distributions(project)?.into_iter()
+        .map(|(name, _)| (normalize(&name), name))
+        .collect::<BTreeMap<String, String>>();
+    let installed = dists::installed(&project.venv());
+    let mut problems = vec![];
+
+    for name in &declared {
+        if !imported.contains_key(&normalize(name)) {
+            problems.push(format!("'{}' is in requirements.txt but never imported.", name));
+        }
+    }
+
+    let declared = declared.iter().map(|name| normalize(name)).chain(grouped).collect::<BTreeSet<String>>();
+    for (key, name) in &imported {
+        if !declared.contains(key) {
+            problems.push(format!("'{}' is imported but not in requirements.txt or a dependency group.", name));
+        }
+    }
+
+    // Dependencies of declared or imported packages are installed for a reason.
+    let needed = closure(declared.iter().chain(imported.keys()).cloned(), &installed);
+    for dist in &installed {
+        if is_locked(&dist.name) && !needed.contains(&normalize(&dist.name)) {
+            problems.push(format!("'{}' is installed in the venv, but neither declared nor imported, nor needed by a package that is.", dist.name));
+        }
+    }
+
+    if problems.is_empty() {
+        println!("{}", Color::Green.paint("√ |> Imports, requirements and the venv agree."));
+        return Ok(());
+    }
+
+    for problem in &problems {
+        println!("{}", Color::Red.paint(format!("X |> {}", problem)));
+    }
+    Err(PieError::Failed(format!("{} problem(s) found.", problems.len())))
+}
@@ -1,33 +1,32 @@
-use std::{env, fs, path::Path, process};
+use std::{fs, path::{Path, PathBuf}, process::Command};
 
 use ansi_term::Color;
-use clap::{Parser, Subcommand};
-use pie::{Result, run_cmd};
+use clap::{Args, Subcommand};
+use pie::{CmdOutcome, PieError, Result, run_cmd, run_cmd_with};
 use spinach::{Spinach, Spinner};
 
-use crate::config::ProjectConfig;
-
-use super::out_commands;
+use super::{deps::DepsSubCommands, interpreters::PythonSubCommands, lock, tree::TreeFormat, venv::VenvSubCommands};
+use crate::{config::{ProjectFile, VersionFile}, project::Project, python::{dists, imports, osv::Level, pep440, requirements, stdlib, venv}, semver::{BumpLevel, Version}};
 
 /////////////////////////////////////////////////////////////////////
-#[derive(Parser, Debug)]
-#[clap(
-    author = "Terroid", 
-    version = "0.0.1", 
-    about = "A Loose structured and easy to use python project manager.", 
-    long_about = "A Loose structured and easy to use python project manager. Different sets of commands can be accessed by using the cli inside a project directory or outside of it."
-    )
-]
-pub struct InArgs {
-    #[clap(subcommand)]
-    pub command: InSubCommands,
-}
-
 #[derive(Debug, Subcommand)]
 pub enum InSubCommands{
 
     /// Displays or edits the Project's version.
-    Ver{ ver: Option<String> },
+    #[clap(
+        args_conflicts_with_subcommands = true,
+        long_about("Displays or edits the Project's version. Versions must be valid SemVer (MAJOR.MINOR.PATCH[-PRE][+BUILD]), and a version lower than the current one is refused unless --force is passed.")
+    )]
+    Ver{
+        /// The new version.
+        ver: Option<String>,
+
+        #[clap(flatten)]
+        flags: VerFlags,
+
+        #[clap(subcommand)]
+        bump: Option<VerSubCommands>
+    },
 
     /// Runs the python project
     Run{
@@ -35,26 +34,47 @@ pub enum InSubCommands{
         args: Vec<String> 
     },
 
-    /// Runs `pip show` inside the venv
-    Show{
+    /// Runs a task from the 'scripts' section of project.json.
+    #[clap(
+        trailing_var_arg = true,
+        long_about("Runs a task from the 'scripts' section of project.json, with the venv first on PATH. Tasks listed as dependencies run first, and any extra arguments are passed on to the named task's command.")
+    )]
+    Task{
+        /// The task to run.
+        name: Option<String>,
+
+        /// Lists all the tasks.
+        #[clap(short('l'), long)]
+        list: bool,
+
+        /// Arguments passed on to the task.
         #[clap(allow_hyphen_values = true)]
         args: Vec<String>
     },
 
-    /// Runs `pip` inside the venv
-    Pip{
+    /// Runs `pip show` inside the venv
+    Show{
         #[clap(allow_hyphen_values = true)]
         args: Vec<String>
     },
 
-    /// Runs `pip list` inside the venv
-    List{
+    /// Runs `pip` inside the venv
+    Pip{
         #[clap(allow_hyphen_values = true)]
         args: Vec<String>
     },
 
     /// Runs `pip install` inside the venv
+    #[clap(long_about("Runs `pip install` inside the venv. With --group (or --dev), the requirements are recorded in that dependency group of project.json instead of being left to requirements.txt, and without any requirements the whole group is installed. Options for pie go before the requirements."))]
     Install{
+        /// The dependency group the requirements belong to, e.g. 'docs'.
+        #[clap(long, value_name("GROUP"))]
+        group: Option<String>,
+
+        /// Same as '--group dev'.
+        #[clap(long, conflicts_with("group"))]
+        dev: bool,
+
         #[clap(allow_hyphen_values = true)]
         args: Vec<String>
     },
@@ -66,12 +86,97 @@ pub enum InSubCommands{
     },
 
     /// Generates a requirements.txt file for the project.
-    #[clap(long_about("Generates a requirements.txt file for the project. If --install is passed, it will install the packages in requirements.txt file."))]
+    #[clap(long_about("Updates the requirements.txt file of the project from its imports. Existing lines, with their pins, extras, markers and comments, are kept; new requirements are appended and ones that are no longer imported are pointed out. If --install is passed, it will install the packages in requirements.txt file instead."))]
     Reqs{
 
         // Install the packages in requirements.txt file.
         #[clap(long)]        
-        install: bool
+        install: bool,
+
+        /// Prints the changes to requirements.txt as a diff, without writing it.
+        #[clap(long, conflicts_with("install"))]
+        dry_run: bool
+    },
+
+    /// Writes pie.lock with the exact versions installed in the venv.
+    #[clap(long_about("Writes pie.lock with the exact version of every package installed in the venv, the sha256 of the archive it was installed from where pip recorded it or the archive is a local file, and the venv's python version."))]
+    Lock,
+
+    /// Makes the venv match pie.lock exactly.
+    #[clap(long_about("Makes the venv match pie.lock exactly: installs the packages that are missing or at another version, and removes the ones that aren't locked. Packages of dependency groups are only installed for the groups passed with --groups. pip, setuptools and wheel are left alone."))]
+    Sync{
+        /// Comma separated dependency groups to install as well, e.g. 'dev,docs'.
+        #[clap(long, value_name("GROUPS"), use_value_delimiter = true)]
+        groups: Vec<String>
+    },
+
+    /// Shows the packages in the venv that have newer versions on the package index.
+    #[clap(long_about("Shows the packages in the venv that have newer versions on the package index, with the installed version, the newest version requirements.txt allows and the latest one. The index is a PEP 503/691 simple index URL or a local folder, taken from 'index_url' in pie's config.json unless --index is passed. Pre-releases are only shown for packages already on one."))]
+    Outdated{
+        /// The simple index to use instead of 'index_url': a URL, or a local folder of archives or of a simple index.
+        #[clap(long, value_name("URL|DIR"))]
+        index: Option<String>,
+
+        /// Prints the outdated packages as JSON.
+        #[clap(long)]
+        json: bool,
+
+        /// Moves the '==' pins in requirements.txt to the latest versions.
+        #[clap(long, conflicts_with("json"))]
+        upgrade: bool
+    },
+
+    /// Shows the dependency tree of the packages in the venv.
+    #[clap(long_about("Shows the dependency tree of the packages in the venv, read from their installed metadata. Packages in requirements.txt are highlighted, and requirements the installed versions don't satisfy, missing packages and dependency cycles are reported."))]
+    Tree{
+        /// Shows the packages that depend on <PACKAGE> instead: why is it installed?
+        #[clap(long, value_name("PACKAGE"))]
+        reverse: Option<String>,
+
+        #[clap(long, arg_enum, default_value("text"))]
+        format: TreeFormat
+    },
+
+    /// Shows the licence of every package in the venv.
+    #[clap(long_about("Shows the licence of every package in the venv, read from License-Expression, License or the licence classifiers of its metadata. If project.json has a 'licenses' field with 'allow' and/or 'deny' lists, packages whose licence isn't allowed are pointed out and the command fails."))]
+    Licenses,
+
+    /// Checks the packages in the venv against an offline database of known vulnerabilities.
+    #[clap(long_about("Checks the packages in the venv, or in pie.lock with --lock, against OSV advisories on disk: a folder or zip of OSV JSON records, like osv.dev's PyPI all.zip. The database is 'advisory_db' in pie's config.json unless --db is passed. Prints the affected packages with the advisory ids, severities and fixed versions, and fails if any advisory is at least as severe as --fail-on. Advisories without a severity count as low. Nothing is downloaded."))]
+    Audit{
+        /// The OSV folder or zip to use instead of 'advisory_db'.
+        #[clap(long, value_name("PATH"))]
+        db: Option<PathBuf>,
+
+        /// Audits the versions in pie.lock instead of the venv.
+        #[clap(long)]
+        lock: bool,
+
+        /// The lowest severity that makes the audit fail.
+        #[clap(long, arg_enum, value_name("SEVERITY"), default_value("low"))]
+        fail_on: Level,
+
+        /// Prints the findings as JSON.
+        #[clap(long)]
+        json: bool
+    },
+
+    /// Checks the project's dependencies.
+    Deps{
+        #[clap(subcommand)]
+        command: DepsSubCommands
+    },
+
+    /// Lists the python interpreters found, or pins the one the project uses.
+    Python{
+        #[clap(subcommand)]
+        command: PythonSubCommands
+    },
+
+    /// Manages the project's environment.
+    Venv{
+        #[clap(subcommand)]
+        command: VenvSubCommands
     },
 
     /// Automatically installs all modules used in the project, to the venv.
@@ -89,10 +194,46 @@ pub enum InSubCommands{
         #[clap(short('B'), default_value("main"))]
         /// Branch to push repository to.
         branch: String
+    },
+
+}
+
+#[derive(Debug, Subcommand)]
+pub enum VerSubCommands{
+
+    /// Bumps the Project's version.
+    Bump{
+        /// The part of the version to bump.
+        #[clap(arg_enum)]
+        level: BumpLevel,
+
+        /// Pre-release identifier to use when bumping to a pre-release, e.g. 'rc' or 'beta'.
+        #[clap(long)]
+        pre_id: Option<String>,
+
+        #[clap(flatten)]
+        flags: VerFlags
     }
 
 }
 
+#[derive(Debug, Args)]
+pub struct VerFlags{
+
+    /// Accepts a malformed or downgraded version.
+    #[clap(short('F'), long)]
+    pub force: bool,
+
+    /// Commits project.json and the version_files with the message 'v<version>'.
+    #[clap(long)]
+    pub commit: bool,
+
+    /// Creates an annotated git tag 'v<version>', on the version commit with --commit.
+    #[clap(short('T'), long)]
+    pub tag: bool
+
+}
+
 /////////////////////////////////////////////////////////////////////
 
 fn spinach_log(spinach: &Spinach, frozen_msg: &str, new_message: &str, err: bool){
@@ -103,159 +244,475 @@ fn spinach_log(spinach: &Spinach, frozen_msg: &str, new_message: &str, err: bool
     }
 }
 
-pub fn push(commit_msg: String, remote: String, branch: String) -> Result<()>{
+pub fn push(commit_msg: String, remote: String, branch: String, project: &Project) -> Result<()>{
     println!("{}", Color::Green.paint("|> Pushing to Github..."));
     let spinner = Spinner::new(vec!["-", "\\", "|", "/"], 130);
     let spinach = Spinach::new_with(spinner, "Pushing to Github", spinach::Color::Ignore);
+    let root = project.root.as_os_str();
 
-    let mut err = false;
-
-    run_cmd("git", &vec!["add", "."], false, ||{
-        err = true;
-    }, ||{
-        spinach_log(&spinach, "Added files to git index.", "Committing files to local repo", false);
-    });
-    if err{
+    if !run_cmd("git", &["-C".as_ref(), root, "add".as_ref(), ".".as_ref()], false).success() {
         spinach.stop_with("X |> ", Color::Red.paint("Could not add files to git index.").to_string(), spinach::Color::Ignore);
-        process::exit(1);
+        return Err(PieError::Subprocess(String::from("'git add .' failed.")));
     }
+    spinach_log(&spinach, "Added files to git index.", "Committing files to local repo", false);
 
-    run_cmd("git", &vec!["commit", "-m", &commit_msg], false, ||{
-        err = true;
-    }, ||{
-        spinach_log(&spinach, "Committed files to local repo.", "Pushing files to github", false);
-    });
-    if err {
+    if !run_cmd("git", &["-C".as_ref(), root, "commit".as_ref(), "-m".as_ref(), commit_msg.as_ref()], false).success() {
         spinach.stop_with("X |> ", Color::Red.paint("Could not commit files to local repo.").to_string(), spinach::Color::Ignore);
-        process::exit(1);
+        return Err(PieError::Subprocess(String::from("'git commit' failed. Are there any changes to commit?")));
     }
+    spinach_log(&spinach, "Committed files to local repo.", "Pushing files to github", false);
 
-    run_cmd("git", &vec!["push", &remote, &branch], false, ||{
-        err = true;
-    }, || {
-    });
-    if err{
+    if !run_cmd("git", &["-C".as_ref(), root, "push".as_ref(), remote.as_ref(), branch.as_ref()], false).success() {
         spinach.stop_with("X |> ", Color::Red.paint("Could not push files to github.").to_string(), spinach::Color::Ignore);
-        process::exit(1);
+        return Err(PieError::Subprocess(format!("'git push {} {}' failed.", remote, branch)));
     }
     spinach.stop_with("√ |> ", Color::Green.paint("Pushed files to github.").to_string(), spinach::Color::Ignore);
 
     Ok(())
 }
 
-pub fn auto_install(is_in_proj: Option<ProjectConfig>) -> Result<()> {
+pub fn auto_install(project: &Project) -> Result<()> {
 
-    let result = reqs(false, true, is_in_proj)?;
-    reqs(true, true, result)?;
+    reqs(false, false, true, project)?;
+    reqs(true, false, true, project)?;
     Ok(())
 }
 
-pub fn reqs(install: bool, display_progress: bool, is_in_proj: Option<ProjectConfig>) -> Result<Option<ProjectConfig>> {
+/// Installs requirements.txt, or merges the requirements detected from the project's imports into it.
+pub fn reqs(install: bool, dry_run: bool, display_progress: bool, project: &Project) -> Result<()> {
     if install{
-        let req_txt = Path::new("requirements.txt");
+        let req_txt = project.requirements();
         if req_txt.exists(){
-            let is_in_proj = run_pip("install", &mut vec!["-r", Path::new("..").join("requirements.txt").to_str().unwrap()].into_iter().map(String::from).collect(), false, is_in_proj)?;
-            
+            let outcome = run_pip("install", &mut vec![String::from("-r"), req_txt.to_string_lossy().into_owned()], false, project)?;
+
+            if !outcome.success() {
+                return Err(PieError::Subprocess(String::from("Could not install the packages in 'requirements.txt'.")));
+            }
             if display_progress{ println!("{}", Color::Green.bold().paint("√ |> Installed packages in 'requirements.txt'")); }
 
-            return Ok(is_in_proj);
+            return Ok(());
         }
         
         if display_progress{ println!("{}", Color::Red.bold().paint("X |> Could not find 'requirements.txt'")); }
-        return Ok(is_in_proj);
+        return Ok(());
         
     }
 
-    run_cmd("pipreqs", &vec![String::from("--force")], false, ||{
-        if display_progress{ println!("{}", Color::Red.bold().paint("X |> Command 'pipreqs' failed. Please check if 'pipreqs' is installed, if not, install it from pip/pypi. If pipreqs is installed, please check your project for correct import statements")); }
-    }, || {
-        if display_progress{ println!("{}", Color::Green.bold().paint("√ |> Written requirements in 'requirements.txt'")); }
-    });
+    let req_txt = project.requirements();
+    let existing = if req_txt.exists() { fs::read_to_string(&req_txt)? } else { String::new() };
+    let merge = requirements::merge(&existing, &detect_requirements(project)?);
+
+    if dry_run {
+        let diff = requirements::diff(&existing, &merge.text, &merge.unused);
+        if diff.is_empty() {
+            println!("{}", Color::Green.paint("|> No changes to 'requirements.txt'."));
+            return Ok(());
+        }
+
+        println!("{}", Color::Green.paint("|> Changes to 'requirements.txt' (dry run, nothing was written):"));
+        println!("--- requirements.txt\n+++ requirements.txt");
+        for line in diff {
+            match line.chars().next() {
+                Some('+') => println!("{}", Color::Green.paint(line)),
+                Some('-') => println!("{}", Color::Red.paint(line)),
+                Some('?') => println!("{}", Color::Yellow.paint(line)),
+                Some('@') => println!("{}", Color::Cyan.paint(line)),
+                _ => println!("{}", line),
+            }
+        }
+        if !merge.unused.is_empty() {
+            println!("{}", Color::Yellow.paint("? |> Lines marked '?' are no longer imported. They are kept, remove them if they aren't needed."));
+        }
+        return Ok(());
+    }
+
+    fs::write(&req_txt, &merge.text)?;
+    if display_progress{ println!("{}", Color::Green.bold().paint(format!("√ |> Written requirements in 'requirements.txt' ({} new)", merge.added.len()))); }
+
+    if display_progress {
+        for line in &merge.unused {
+            println!("{}", Color::Yellow.paint(format!("? |> '{}' is no longer imported, remove it from 'requirements.txt' if it isn't needed.", line)));
+        }
+    }
+
+    Ok(())
+}
+
+/// The imported distributions that belong in requirements.txt. Those of dependency groups, like
+/// test runners imported by the tests, aren't runtime requirements.
+fn detect_requirements(project: &Project) -> Result<Vec<(String, Option<String>)>> {
+    let grouped = grouped_distributions(project);
+    Ok(imported_distributions(project)?.into_iter().filter(|(name, _)| !grouped.contains(&dists::normalize(name))).collect())
+}
+
+/// The distributions the project imports, with the installed version of those in the venv.
+/// The project's modules and the standard library of the venv's python are left out.
+pub fn imported_distributions(project: &Project) -> Result<Vec<(String, Option<String>)>> {
+    let scan = imports::scan(&project.working_dir())?;
+    let version = project.python_version().and_then(|version| venv::minor_version(&version)).unwrap_or(stdlib::LATEST);
+    let installed = dists::installed(&project.venv());
+
+    Ok(scan.third_party(version).map(|module| dists::distribution_of(module, &installed)).collect())
+}
+
+/// Normalized names of the distributions in the project's dependency groups.
+pub fn grouped_distributions(project: &Project) -> Vec<String> {
+    project.config.groups.iter().flatten()
+        .flat_map(|(_, requirements)| requirements.iter().filter_map(|line| requirements::requirement_name(line)))
+        .map(|name| dists::normalize(&name))
+        .collect()
+}
+
+/// Runs `pip install`. With a dependency `group`, the requirements installed are recorded in it,
+/// or if none are given, the requirements already in it are installed.
+pub fn install(group: Option<String>, mut args: Vec<String>, project: &Project) -> Result<CmdOutcome> {
+    let group = match group {
+        Some(group) => group,
+        None => return run_pip("install", &mut args, true, project),
+    };
+    let mut groups = project.config.groups.clone().unwrap_or_default();
+    let new = requirements::install_requirements(&args);
+
+    if new.is_empty() {
+        match groups.get(&group) {
+            Some(requirements) => args.extend(requirements.iter().cloned()),
+            None => return Err(PieError::Failed(format!("There is no dependency group '{}' in 'project.json'.", group))),
+        }
+        return run_pip("install", &mut args, true, project);
+    }
+
+    let outcome = run_pip("install", &mut args, true, project)?;
+    if !outcome.success() {
+        return Ok(outcome);
+    }
 
-    Ok(is_in_proj)
+    // A requirement already in the group is replaced, so it can be re-pinned.
+    let requirements = groups.entry(group.to_owned()).or_default();
+    for line in new {
+        let name = requirements::requirement_name(&line).map(|name| dists::normalize(&name));
+        match requirements.iter().position(|existing| requirements::requirement_name(existing).map(|name| dists::normalize(&name)) == name) {
+            Some(i) => requirements[i] = line,
+            None => requirements.push(line),
+        }
+    }
+
+    let mut file = ProjectFile::open(&project.file())?;
+    file.set("groups", &groups)?;
+    file.save()?;
+    println!("{}", Color::Green.paint(format!("√ |> Added to the '{}' group in 'project.json'.", group)));
+
+    Ok(outcome)
 }
 
-pub fn run_pip(cmd: &str, args: &mut Vec<String>, should_display_output: bool, is_in_proj: Option<ProjectConfig>) -> Result<Option<ProjectConfig>> {
+pub fn run_pip(cmd: &str, args: &mut Vec<String>, should_display_output: bool, project: &Project) -> Result<CmdOutcome> {
 
-    if cmd != ""{
+    if !cmd.is_empty(){
         args.insert(0, cmd.to_string());
     }
 
-    #[cfg(windows)]
-    let is_in_proj = run_venv_cmd("pip", args, RunPy::DontRun, should_display_output, is_in_proj)?;
+    run_venv_cmd(project.env.pip(&project.venv(), args), "pip", should_display_output, project)
+}
+
+pub fn run(mut args: Vec<String>, project: &Project) -> Result<CmdOutcome> {
+    args.insert(0, project.config.entry_point.to_string());
+
+    let mut command = Command::new(project.python());
+    command.args(&args);
+    run_venv_cmd(command, "python", true, project)
+}
+
+/// Runs `command` inside the project's environment, from its working directory.
+fn run_venv_cmd(mut command: Command, name: &str, should_display_output: bool, project: &Project) -> Result<CmdOutcome> {
 
-    #[cfg(not(windows))]
-    let is_in_proj = run_venv_cmd("pip3", args, RunPy::DontRun, should_display_output, is_in_proj)?;
+    let venv_path = project.venv();
+    ensure_venv(project)?;
+    warn_python_mismatch(project);
+    project.env.activate(&venv_path, &mut command)?;
+    command.current_dir(project.working_dir());
 
-    Ok(is_in_proj)
+    match run_cmd_with(&mut command, should_display_output) {
+        CmdOutcome::SpawnFailed(e) => Err(PieError::Venv(format!("Could not run {}: {}. If the project was moved, run 'pie venv repair', or else 'pie venv recreate'.", name, e))),
+        outcome => Ok(outcome),
+    }
 }
 
-enum RunPy{
-    Run,
-    DontRun
+/// Makes sure the project's environment exists before a command uses it: creates it if it is
+/// missing, unless `--no-auto-venv` was passed. One left in the central store by moving the
+/// project is pointed out instead, so it isn't replaced by a new one.
+pub fn ensure_venv(project: &Project) -> Result<()> {
+    let venv_path = project.venv();
+    if venv_path.exists() {
+        return Ok(());
+    }
+    if let Some(old) = project.abandoned_venvs()?.first() {
+        return Err(PieError::Venv(format!("The project was moved or renamed, and its venv is still at '{}'. Run 'pie venv repair' to move it, or 'pie venv create' to make a new one.", old.display())));
+    }
+    if !project.auto_venv {
+        return Err(PieError::Venv(format!("No venv at '{}'. Run 'pie venv create', or run without --no-auto-venv to let pie create it.", venv_path.display())));
+    }
+    println!("{}", Color::Yellow.paint("|> Venv Not Found, creating it."));
+    create_venv(project)
 }
 
-pub fn run(mut args: Vec<String>, is_in_proj: ProjectConfig) -> Result<()> {
-    #[cfg(windows)]
-    run_venv_cmd("python", &mut args, RunPy::Run, true, Some(is_in_proj))?;
+/// Creates the project's environment and installs pie.lock into it if the project has one, or
+/// else requirements.txt. If either step fails, the half made environment is removed, so the
+/// next command starts over.
+pub fn create_venv(project: &Project) -> Result<()> {
+    let venv_path = project.venv();
+    let spinner = Spinner::new(vec!["-", "\\", "|", "/"], 130);
+    let spinach = Spinach::new_with(spinner, Color::Yellow.paint(format!("Creating a {} environment", project.env.name())).to_string(), spinach::Color::Ignore);
 
-    #[cfg(not(windows))]
-    run_venv_cmd("python3", &mut args, RunPy::Run, true, Some(is_in_proj))?;
+    if let Err(e) = project.env.create(&venv_path, project.python_pin().as_ref()) {
+        spinach.stop_with("X |> ", Color::Red.paint("Could not create the venv.").to_string(), spinach::Color::Ignore);
+        remove_venv(&venv_path)?;
+        return Err(e);
+    }
+    spinach.stop_with("√ |> ", Color::Green.paint(format!("Created a {} environment at '{}'.", project.env.name(), venv_path.display())).to_string(), spinach::Color::Ignore);
+    project.claim_venv()?;
 
+    if project.lock_file().exists() {
+        if let Err(e) = lock::sync(project, &[]) {
+            remove_venv(&venv_path)?;
+            return Err(e);
+        }
+        return Ok(());
+    }
+
+    let requirements_txt = fs::read_to_string(project.requirements()).unwrap_or_default();
+    if requirements_txt.lines().filter_map(requirements::requirement_name).next().is_none() {
+        return Ok(());
+    }
+
+    println!("{}", Color::Yellow.paint("|> Installing requirements from 'requirements.txt'."));
+    let mut command = project.env.pip(&venv_path, &[String::from("install"), String::from("-r"), project.requirements().to_string_lossy().into_owned()]);
+    let installed = project.env.activate(&venv_path, &mut command).map(|_| run_cmd_with(&mut command, true));
+    if !installed.as_ref().is_ok_and(CmdOutcome::success) {
+        remove_venv(&venv_path)?;
+        return Err(PieError::Subprocess(String::from("Could not install the requirements from 'requirements.txt', the new venv was removed.")));
+    }
+    println!("{}", Color::Green.paint("√ |> Installed the requirements from 'requirements.txt'."));
     Ok(())
 }
 
-fn run_venv_cmd(main_cmd: &str, args: &mut Vec<String>, run: RunPy, should_display_output: bool, is_in_proj: Option<ProjectConfig>) -> Result<Option<ProjectConfig>> {
+pub fn remove_venv(venv_path: &Path) -> Result<()> {
+    if venv_path.exists() {
+        remove_dir_all::remove_dir_all(venv_path)?;
+    }
+    Ok(())
+}
 
-    let project_conf = if let None = is_in_proj{
-        out_commands::is_in_proj(&env::current_dir().unwrap())
-    } else {
-        is_in_proj
+/// Warns when the environment's python isn't the one the project pins, e.g. after the pin changed.
+pub fn warn_python_mismatch(project: &Project) {
+    let (pin, version) = match (project.python_pin(), project.python_version().and_then(|version| version.parse::<pep440::Version>().ok())) {
+        (Some(pin), Some(version)) => (pin, version),
+        _ => return,
     };
-    
-    if let Some(conf) = &project_conf{
+    if !pin.accepts(&version) {
+        println!("{}", Color::Yellow.paint(format!("? |> The venv has python {}, but the project asks for '{}'. Run 'pie venv recreate' to rebuild it.", version, pin)));
+    }
+}
 
-        let venv_path = Path::new("venv");
+pub fn version(ver: Option<String>, bump: Option<VerSubCommands>, flags: VerFlags, project: &mut Project) -> Result<()> {
 
-        if !venv_path.exists(){
+    let proj_ver = match &project.config.version {
+        Some(proj_ver) => proj_ver.to_owned(),
+        None => return Err(PieError::Failed(String::from("This project.json doesn't contain a 'version' field."))),
+    };
 
-            let cmds = if cfg!(windows) {("python", "pip")} else {("python3", "pip3")};
+    let (new_ver, flags) = match (ver, bump) {
+        (_, Some(VerSubCommands::Bump { level, pre_id, flags })) => {
+            let current = proj_ver.parse::<Version>()
+                .map_err(|e| PieError::Failed(format!("Current version '{}' is not valid SemVer: {}. Set a valid version first.", proj_ver, e)))?;
+            let next = current.bump(level, pre_id.as_deref()).to_string();
+            if let Err(e) = next.parse::<Version>() {
+                return Err(PieError::Failed(format!("Invalid pre-release identifier: {}", e)));
+            }
+            (next, flags)
+        },
+        (Some(ver), None) => (ver, flags),
+        (None, None) => {
+            println!("{}{}", Color::Green.paint("|> Current Version: "), Color::Green.bold().paint(&proj_ver));
+            return Ok(());
+        }
+    };
 
-            println!("{}", Color::Red.paint("X |> Venv Not Found. Initialising a venv. Please wait"));
-            run_cmd(cmds.0, &vec!["-m", "venv", "venv"], false, ||{}, ||{});
-            run_venv_cmd(cmds.1, &mut vec!["install", "-r", Path::new("..").join("requirements.txt").to_str().unwrap()].into_iter().map(String::from).collect(), RunPy::DontRun, false, project_conf)?;
-            println!("{}", Color::Green.paint("√ |> Initialised a venv, and installed requirements from 'requirements.txt'. Please restart the program."));
-            process::exit(1);
+    match (new_ver.parse::<Version>(), proj_ver.parse::<Version>()) {
+        (Err(e), _) if !flags.force => {
+            return Err(PieError::Failed(format!("'{}' is not valid SemVer: {}. Use '--force' to set it anyway.", new_ver, e)));
+        },
+        (Ok(new), Ok(current)) if new < current && !flags.force => {
+            return Err(PieError::Failed(format!("'{}' is lower than the current version '{}'. Use '--force' to downgrade.", new_ver, proj_ver)));
+        },
+        _ => {}
+    }
 
+    // Without text before `{version}` there is nothing to find the old version by.
+    for target in project.config.version_files.iter().flatten() {
+        if let VersionFile::Pattern { path, pattern } = target {
+            if pattern != "{version}" && pattern.split_once("{version}").is_none_or(|(prefix, _)| prefix.is_empty()) {
+                return Err(PieError::Config(format!("The version_files pattern '{}' for '{}' must contain '{{version}}' with text before it.", pattern, path)));
+            }
         }
-    
-        env::set_current_dir(&conf.working_directory)?;
-        if let RunPy::Run = run{
-            args.insert(0, conf.entry_point.to_string());
+    }
+
+    // Every file is read and rewritten in memory first, so nothing is written if one of them fails.
+    let mut updates = vec![];
+    for target in project.config.version_files.iter().flatten() {
+        let (path, pattern) = match target {
+            VersionFile::Path(path) if path.ends_with(".py") => (path, None),
+            VersionFile::Path(path) => (path, Some("{version}")),
+            VersionFile::Pattern { path, pattern } => (path, Some(pattern.as_str())),
+        };
+
+        let content = fs::read_to_string(project.root.join(path))
+            .map_err(|e| PieError::Failed(format!("Could not read '{}' from version_files: {}. Nothing was changed.", path, e)))?;
+        let updated = match pattern {
+            Some("{version}") if content.trim().is_empty() => Some(format!("{}\n", new_ver)),
+            Some("{version}") => Some(content.replacen(content.trim(), &new_ver, 1)),
+            Some(pattern) => replace_in_pattern(&content, pattern, &new_ver),
+            None => replace_dunder_version(&content, &new_ver),
+        };
+        match updated {
+            Some(updated) => updates.push((path, updated)),
+            None => return Err(PieError::Failed(format!("Could not find a version to replace in '{}'. Nothing was changed.", path))),
         }
+    }
 
-        let dir_venv = if cfg!(windows) {"Scripts"} else {"bin"};
+    let tag = format!("v{}", new_ver);
+    if flags.tag && run_cmd("git", &["-C", &project.root.to_string_lossy(), "rev-parse", "--quiet", "--verify", &format!("refs/tags/{}", tag)], false).success() {
+        return Err(PieError::Failed(format!("The git tag '{}' already exists. Nothing was changed.", tag)));
+    }
 
-        let path = Path::new("..").join("venv").join(dir_venv).join(main_cmd);
-        let main_cmd = path.to_str().unwrap();
+    let mut project_file = ProjectFile::open(&project.file())?;
+    project_file.set("version", &new_ver)?;
+    project_file.save()?;
+    project.config.version = Some(new_ver.to_owned());
+    println!("{}{} → {}", Color::Green.paint("√ |> Version: "), Color::Green.bold().paint(&proj_ver), Color::Green.bold().paint(&new_ver));
+
+    let mut changed = vec![String::from("project.json")];
+    for (path, updated) in updates {
+        fs::write(project.root.join(path), updated)?;
+        println!("{}", Color::Green.paint(format!("√ |> Updated version in '{}'", path)));
+        changed.push(path.to_owned());
+    }
 
-        run_cmd(main_cmd, &args, should_display_output, || {}, || {});
+    let root = project.root.to_string_lossy();
+    if flags.commit {
+        // Only the version files go into the commit, whatever else is staged stays staged.
+        let mut add = vec!["-C", &root, "add", "--"];
+        let mut commit = vec!["-C", &root, "commit", "--only", "-m", &tag, "--"];
+        add.extend(changed.iter().map(String::as_str));
+        commit.extend(changed.iter().map(String::as_str));
+        if !run_cmd("git", &add, false).success() || !run_cmd("git", &commit, false).success() {
+            return Err(PieError::Subprocess(String::from("Could not commit the version change to git.")));
+        }
+        println!("{}{}", Color::Green.paint("√ |> Committed the version change: "), Color::Green.bold().paint(&tag));
+    }
+    if flags.tag {
+        if !run_cmd("git", &["-C", &root, "tag", "-a", &tag, "-m", &format!("Version {}", new_ver)], false).success() {
+            return Err(PieError::Subprocess(format!("Could not create git tag '{}'.", tag)));
+        }
+        println!("{}{}", Color::Green.paint("√ |> Created git tag: "), Color::Green.bold().paint(tag));
     }
 
-    Ok(project_conf)
+    Ok(())
 }
 
-pub fn version(ver: Option<String>, is_in_proj: &mut ProjectConfig) -> Result<()> {
+/// Replaces the value of every `__version__ = "..."` assignment.
+fn replace_dunder_version(content: &str, new_ver: &str) -> Option<String> {
+    let mut found = false;
+    let lines = content.split_inclusive('\n').map(|line| {
+        let rest = line.trim_start().strip_prefix("__version__").map(str::trim_start);
+        if let Some(value) = rest.and_then(|rest| rest.strip_prefix('=')).map(str::trim_start) {
+            if let Some(quote) = value.chars().next().filter(|c| *c == '"' || *c == '\'') {
+                if let Some(len) = value[1..].find(quote) {
+                    let start = line.len() - value.len() + 1;
+                    found = true;
+                    return format!("{}{}{}", &line[..start], new_ver, &line[start + len..]);
+                }
+            }
+        }
+        line.to_string()
+    }).collect::<String>();
+
+    found.then_some(lines)
+}
 
-    if let Some(proj_ver) = &is_in_proj.version {
-        println!("{}{}", Color::Green.paint("|> Current Version: "), Color::Green.bold().paint(proj_ver));
-        if let Some(inp_version) = ver{
-            is_in_proj.version = Some(inp_version.to_owned());
-            fs::write("project.json", serde_json::to_string_pretty(&is_in_proj)?)?;
-            println!("{}{}", Color::Green.paint("√ |> New Version: "), Color::Green.bold().paint(inp_version));
+/// Replaces whatever stands in for `{version}` wherever the rest of `pattern` matches.
+/// The text before `{version}` is what finds the old version, so `pattern` must start with some.
+fn replace_in_pattern(content: &str, pattern: &str, new_ver: &str) -> Option<String> {
+    let (prefix, suffix) = pattern.split_once("{version}").filter(|(prefix, _)| !prefix.is_empty())?;
+    let mut out = String::new();
+    let mut rest = content;
+    let mut found = false;
+
+    while let Some(start) = rest.find(prefix) {
+        let after = &rest[start + prefix.len()..];
+        let end = if suffix.is_empty() {
+            after.find(|c: char| c.is_whitespace() || c == '"' || c == '\'').unwrap_or(after.len())
+        } else {
+            match after.find(suffix) {
+                Some(end) => end,
+                None => break,
+            }
+        };
+
+        let old = &after[..end];
+        if old.is_empty() || old.contains(char::is_whitespace) {
+            out.push_str(&rest[..start + prefix.len()]);
+            rest = after;
+            continue;
         }
-        return Ok(());
+
+        out.push_str(&rest[..start + prefix.len()]);
+        out.push_str(new_ver);
+        rest = &after[end..];
+        found = true;
     }
-    println!("{}", Color::Red.paint("This project.json doesn't contain a 'version' field.")); 
-    Ok(())
-}
\ No newline at end of file
+    out.push_str(rest);
+
+    found.then_some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dunder_version_in_either_quotes() {
+        assert_eq!(replace_dunder_version("__version__ = \"1.0.0\"\n", "1.1.0").unwrap(), "__version__ = \"1.1.0\"\n");
+        assert_eq!(replace_dunder_version("x = 1\n__version__='1.0.0'  # release\n", "2.0.0").unwrap(), "x = 1\n__version__='2.0.0'  # release\n");
+    }
+
+    #[test]
+    fn dunder_version_everywhere_or_nowhere() {
+        let content = "__version__ = '1.0'\nif x:\n    __version__ = \"1.0\"\n";
+        assert_eq!(replace_dunder_version(content, "1.1").unwrap(), "__version__ = '1.1'\nif x:\n    __version__ = \"1.1\"\n");
+        assert_eq!(replace_dunder_version("version = '1.0'\n__version__ = get()\n", "1.1"), None);
+    }
+
+    #[test]
+    fn pattern_replaces_every_match() {
+        let content = "![v](badge/version-1.0.0-blue) and ![v](badge/version-1.0.0-blue)";
+        assert_eq!(replace_in_pattern(content, "version-{version}-blue", "1.1.0").unwrap(), "![v](badge/version-1.1.0-blue) and ![v](badge/version-1.1.0-blue)");
+        assert_eq!(replace_in_pattern("no badge here", "version-{version}-blue", "1.1.0"), None);
+    }
+
+    #[test]
+    fn pattern_without_suffix_ends_at_whitespace_or_quote() {
+        assert_eq!(replace_in_pattern("version: 1.0.0\nname: x", "version: {version}", "2.0.0").unwrap(), "version: 2.0.0\nname: x");
+        assert_eq!(replace_in_pattern("v = \"1.0\"", "v = \"{version}", "2.0").unwrap(), "v = \"2.0\"");
+    }
+
+    #[test]
+    fn pattern_skips_empty_and_spaced_candidates() {
+        assert_eq!(replace_in_pattern("v--x v-a b-x v-1.0-x", "v-{version}-x", "2.0").unwrap(), "v--x v-a b-x v-2.0-x");
+    }
+
+    #[test]
+    fn pattern_without_prefix_matches_nothing() {
+        assert_eq!(replace_in_pattern("1.0.0-blue 1.0.0-blue", "{version}-blue", "2.0.0"), None);
+        assert_eq!(replace_in_pattern("1.0.0", "no placeholder", "2.0.0"), None);
+    }
+}
+
@@ -0,0 +1,94 @@
+use std::{env, path::{Component, Path, PathBuf}};
+
+use ansi_term::Color;
+use clap::Subcommand;
+use pie::{PieError, Result};
+
+use crate::{config::ProjectFile, project::Project, python::interpreters::{self, PythonPin}};
+
+use super::in_commands::warn_python_mismatch;
+
+#[derive(Debug, Subcommand)]
+pub enum PythonSubCommands {
+    /// Lists the python interpreters found on this machine.
+    #[clap(long_about("Lists the python interpreters found on PATH, in pyenv (its shims and installed versions) and in the usual system folders, newest first. Those that satisfy the project's pin are highlighted, and the one a new environment would be made with is marked with '*'."))]
+    List,
+
+    /// Pins the python the project's environment is made with.
+    #[clap(long_about("Pins the python the project's environment is made with, by setting 'python' in project.json. Takes a version like '3.11', version specifiers like '>=3.10,<3.13', or the path of an interpreter. Fails if no interpreter found satisfies it."))]
+    Use {
+        /// A version, version specifiers or an interpreter's path.
+        python: String
+    },
+}
+
+/// Lists the interpreters found, pointing out those the project's pin allows.
+pub fn list(project: &Project) -> Result<()> {
+    let pin = project.python_pin();
+    let interpreters = interpreters::discover();
+    if interpreters.is_empty() {
+        return Err(PieError::MissingInterpreter(String::from("no python found on PATH, in pyenv or in the system folders.")));
+    }
+
+    match (&pin, &project.config.python) {
+        (Some(pin), Some(_)) => println!("{}", Color::Green.paint(format!("|> This project pins python '{}' in 'project.json'.", pin))),
+        (Some(pin), None) => println!("{}", Color::Green.paint(format!("|> This project pins python '{}' in '.python-version'.", pin))),
+        (None, _) => println!("{}", Color::Green.paint("|> This project doesn't pin a python, new environments use 'python3' from PATH.")),
+    }
+
+    // A pinned path allows that interpreter only, specifiers allow every version they match.
+    let chosen = match &pin {
+        Some(PythonPin::Path(path)) => interpreters::probe(path).map(|interpreter| interpreter.path),
+        Some(PythonPin::Version { specifiers, .. }) => interpreters.iter().find(|interpreter| specifiers.matches(&interpreter.version)).map(|interpreter| interpreter.path.to_owned()),
+        None => None,
+    };
+    let width = interpreters.iter().map(|interpreter| interpreter.version.to_string().len()).max().unwrap_or(0);
+    for interpreter in &interpreters {
+        let is_chosen = chosen.as_ref() == Some(&interpreter.path);
+        let line = format!("{} {:<w$}  {}", if is_chosen {"*"} else {" "}, interpreter.version.to_string(), interpreter.path.display(), w = width);
+        let allowed = match &pin {
+            Some(PythonPin::Version { specifiers, .. }) => specifiers.matches(&interpreter.version),
+            _ => is_chosen,
+        };
+        if allowed {
+            println!("{}", Color::Green.bold().paint(line));
+        } else {
+            println!("{}", line);
+        }
+    }
+
+    warn_python_mismatch(project);
+    Ok(())
+}
+
+/// Sets `python` in project.json, once an interpreter satisfying it is found.
+pub fn use_python(python: &str, project: &mut Project) -> Result<()> {
+    let pin = match python.parse::<PythonPin>().map_err(PieError::Failed)? {
+        // A path typed from a subdirectory is relative to it, it is stored relative to the project root
+        // when it is inside the project, so it keeps working from anywhere and after moving the project.
+        PythonPin::Path(path) if path.is_relative() => {
+            let path = env::current_dir()?.join(path).components().fold(PathBuf::new(), |mut path, component| {
+                match component {
+                    Component::ParentDir => { path.pop(); },
+                    component => path.push(component),
+                }
+                path
+            });
+            PythonPin::Path(path.strip_prefix(&project.root).map(Path::to_owned).unwrap_or(path))
+        },
+        pin => pin,
+    };
+    let interpreter = match &pin {
+        PythonPin::Path(path) => PythonPin::Path(project.root.join(path)).resolve()?,
+        pin => pin.resolve()?,
+    };
+
+    let mut file = ProjectFile::open(&project.file())?;
+    file.set("python", &pin)?;
+    file.save()?;
+    project.config.python = Some(pin.clone());
+
+    println!("{}", Color::Green.paint(format!("√ |> Pinned python '{}' in 'project.json': python {} at {}.", pin, interpreter.version, interpreter.path.display())));
+    warn_python_mismatch(project);
+    Ok(())
+}
@@ -0,0 +1,130 @@
+use ansi_term::Color;
+use pie::{PieError, Result};
+use spdx::{LicenseItem, LicenseReq, Licensee};
+
+use crate::{config::LicensePolicy, project::Project, python::{dists, license::{license_of, License}, lock::is_locked}};
+
+use super::in_commands::ensure_venv;
+
+/// Prints the licence of every package in the venv. If project.json has a `licenses` policy,
+/// fails when a package's licence is denied, or isn't in a non-empty allow list.
+pub fn licenses(project: &Project) -> Result<()> {
+    ensure_venv(project)?;
+
+    let policy = project.config.licenses.clone();
+    let packages = dists::installed(&project.venv()).into_iter()
+        .filter(|dist| is_locked(&dist.name))
+        .map(|dist| {
+            let license = license_of(&dist);
+            let verdict = policy.as_ref().map(|policy| verdict(&license, policy));
+            (dist, license, verdict)
+        })
+        .collect::<Vec<_>>();
+
+    if let Some(license) = &project.config.license {
+        println!("{}", Color::Green.paint(format!("|> This project is licensed under {}.", license)));
+    }
+
+    let name_width = packages.iter().map(|(dist, _, _)| dist.name.len()).chain(["Package".len()]).max().unwrap_or(0);
+    let version_width = packages.iter().map(|(dist, _, _)| dist.version.len()).chain(["Version".len()]).max().unwrap_or(0);
+    println!("{}", Color::Green.bold().paint(format!("{:<nw$}  {:<vw$}  License", "Package", "Version", nw = name_width, vw = version_width)));
+
+    let mut disallowed = 0;
+    for (dist, license, verdict) in &packages {
+        let text = if license.text.is_empty() { "unknown" } else { &license.text };
+        let line = format!("{:<nw$}  {:<vw$}  {}", dist.name, dist.version, text, nw = name_width, vw = version_width);
+        match verdict {
+            Some(Err(reason)) => {
+                disallowed += 1;
+                println!("{}", Color::Red.paint(format!("{}  ({})", line, reason)));
+            },
+            _ => println!("{}", line),
+        }
+    }
+
+    if disallowed > 0 {
+        return Err(PieError::Failed(format!("{} package(s) with a licence the 'licenses' policy in 'project.json' doesn't allow.", disallowed)));
+    }
+    if policy.is_some() {
+        println!("{}", Color::Green.paint("√ |> Every licence is allowed."));
+    }
+    Ok(())
+}
+
+/// Whether a package may be used under `policy`. An SPDX expression is allowed if some choice
+/// between its `OR` alternatives avoids every denied licence and, with an allow list, only needs allowed ones.
+fn verdict(license: &License, policy: &LicensePolicy) -> std::result::Result<(), &'static str> {
+    match &license.expression {
+        Some(expression) => {
+            if !policy.deny.is_empty() && !expression.evaluate(|req| !policy.deny.iter().any(|entry| matches(entry, req))) {
+                return Err("denied");
+            }
+            if !policy.allow.is_empty() && !expression.evaluate(|req| policy.allow.iter().any(|entry| matches(entry, req))) {
+                return Err("not allowed");
+            }
+        },
+        None => {
+            let listed = |entries: &[String]| entries.iter().any(|entry| entry.eq_ignore_ascii_case(&license.text));
+            if listed(&policy.deny) {
+                return Err("denied");
+            }
+            if !policy.allow.is_empty() && !listed(&policy.allow) {
+                return Err(if license.text.is_empty() { "unknown licence" } else { "not allowed" });
+            }
+        },
+    }
+    Ok(())
+}
+
+/// Whether a policy entry covers a licence requirement: as an SPDX licensee, or else as a family,
+/// the part of the SPDX id before a version (`GPL` covers `GPL-3.0` but not `LGPL-3.0`).
+fn matches(entry: &str, req: &LicenseReq) -> bool {
+    if let Ok(licensee) = Licensee::parse(entry) {
+        return licensee.satisfies(req);
+    }
+    match &req.license {
+        LicenseItem::Spdx { id, .. } => id.name.len() > entry.len()
+            && id.name[..entry.len()].eq_ignore_ascii_case(entry)
+            && id.name[entry.len()..].starts_with('-'),
+        LicenseItem::Other { .. } => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use spdx::Expression;
+
+    use super::*;
+
+    fn check(license: &str, allow: &[&str], deny: &[&str]) -> std::result::Result<(), &'static str> {
+        let license = License { text: license.to_string(), expression: Expression::parse(license).ok() };
+        let policy = LicensePolicy { allow: allow.iter().map(|entry| entry.to_string()).collect(), deny: deny.iter().map(|entry| entry.to_string()).collect() };
+        verdict(&license, &policy)
+    }
+
+    #[test]
+    fn families_match_their_versions_only() {
+        assert_eq!(check("GPL-3.0-only", &[], &["GPL"]), Err("denied"));
+        assert_eq!(check("GPL-2.0-or-later", &[], &["gpl"]), Err("denied"));
+        assert_eq!(check("LGPL-2.1-only", &[], &["GPL"]), Ok(()));
+        assert_eq!(check("AGPL-3.0-only", &[], &["GPL"]), Ok(()));
+        assert_eq!(check("Apache-2.0", &["Apache"], &[]), Ok(()));
+    }
+
+    #[test]
+    fn or_needs_one_acceptable_choice() {
+        assert_eq!(check("MIT OR GPL-3.0-only", &[], &["GPL"]), Ok(()));
+        assert_eq!(check("MIT AND GPL-3.0-only", &[], &["GPL"]), Err("denied"));
+        assert_eq!(check("MIT OR GPL-3.0-only", &["MIT"], &[]), Ok(()));
+        assert_eq!(check("GPL-3.0-only", &["MIT", "Apache-2.0"], &[]), Err("not allowed"));
+        assert_eq!(check("Apache-2.0 AND MIT", &["MIT", "Apache-2.0"], &[]), Ok(()));
+    }
+
+    #[test]
+    fn plain_texts_are_compared_whole() {
+        assert_eq!(check("BSD License", &["bsd license"], &[]), Ok(()));
+        assert_eq!(check("BSD License", &[], &["BSD License"]), Err("denied"));
+        assert_eq!(check("", &["MIT"], &[]), Err("unknown licence"));
+        assert_eq!(check("", &[], &["GPL"]), Ok(()));
+    }
+}
@@ -0,0 +1,127 @@
+use std::{env, fs, process};
+
+use ansi_term::Color;
+use pie::{PieError, Result};
+
+use crate::{project::Project, python::{dists::{self, normalize, Dist}, lock::{is_locked, Lock, LockedPackage}}};
+
+use super::in_commands::{ensure_venv, run_pip};
+
+/// Writes pie.lock from the distributions installed in the project's venv.
+pub fn lock(project: &Project) -> Result<()> {
+    ensure_venv(project)?;
+
+    let runtime = match fs::read_to_string(project.requirements()) {
+        Ok(requirements) => requirements.lines().map(String::from).collect(),
+        Err(_) => vec![],
+    };
+    let lock = Lock::from_venv(&project.venv(), project.python_version(), &runtime, &project.config.groups.clone().unwrap_or_default());
+    lock.write(&project.lock_file())?;
+
+    let hashed = lock.packages.iter().filter(|package| package.sha256.is_some()).count();
+    println!("{}", Color::Green.paint(format!("√ |> Locked {} packages ({} with hashes) in 'pie.lock'.", lock.packages.len(), hashed)));
+    Ok(())
+}
+
+/// Makes the venv match pie.lock exactly: installs what is missing or at another version,
+/// and removes what isn't locked. Packages of dependency groups are only kept for the selected `groups`.
+pub fn sync(project: &Project, groups: &[String]) -> Result<()> {
+    let known = project.config.groups.clone().unwrap_or_default();
+    if let Some(unknown) = groups.iter().find(|group| !known.contains_key(*group)) {
+        return Err(PieError::Failed(format!("There is no dependency group '{}' in 'project.json'.", unknown)));
+    }
+
+    let lock = Lock::read(&project.lock_file())?;
+    ensure_venv(project)?;
+    if let (Some(locked), Some(current)) = (&lock.python, project.python_version()) {
+        if *locked != current {
+            println!("{}", Color::Yellow.paint(format!("? |> 'pie.lock' was made with python {}, the venv has python {}.", locked, current)));
+        }
+    }
+
+    let (extras, missing) = changes(&lock, groups, &dists::installed(&project.venv()));
+
+    if !extras.is_empty() {
+        let mut args = vec![String::from("-y")];
+        args.extend(extras.iter().cloned());
+        if !run_pip("uninstall", &mut args, true, project)?.success() {
+            return Err(PieError::Subprocess(String::from("Could not remove the packages that aren't in 'pie.lock'.")));
+        }
+    }
+
+    if !missing.is_empty() {
+        // pip checks hashes all or nothing, so they are only passed on if every package has one.
+        let hashed = missing.iter().all(|package| package.sha256.is_some());
+        let requirements = missing.iter().map(|package| match &package.sha256 {
+            Some(sha256) if hashed => format!("{}=={} --hash=sha256:{}\n", package.name, package.version, sha256),
+            _ => format!("{}=={}\n", package.name, package.version),
+        }).collect::<String>();
+
+        // Not in the venv, which sync may be about to create.
+        let sync_file = env::temp_dir().join(format!("pie-sync-{}.txt", process::id()));
+        fs::write(&sync_file, requirements)?;
+        let outcome = run_pip("install", &mut vec![String::from("--no-deps"), String::from("-r"), sync_file.to_string_lossy().into_owned()], true, project);
+        fs::remove_file(&sync_file)?;
+
+        if !outcome?.success() {
+            return Err(PieError::Subprocess(String::from("Could not install the packages in 'pie.lock'.")));
+        }
+    }
+
+    println!("{}", Color::Green.paint(format!("√ |> Venv in sync with 'pie.lock': {} installed, {} removed.", missing.len(), extras.len())));
+    Ok(())
+}
+
+/// What syncing the `installed` distributions to `lock` with `groups` takes: the names of those to
+/// remove, and the locked packages to install, that are missing or at another version.
+fn changes<'a>(lock: &'a Lock, groups: &[String], installed: &[Dist]) -> (Vec<String>, Vec<&'a LockedPackage>) {
+    let wanted = lock.packages.iter()
+        .filter(|package| package.groups.is_empty() || package.groups.iter().any(|group| groups.contains(group)))
+        .collect::<Vec<_>>();
+
+    let extras = installed.iter()
+        .filter(|dist| is_locked(&dist.name) && !wanted.iter().any(|package| normalize(&package.name) == normalize(&dist.name)))
+        .map(|dist| dist.name.to_owned())
+        .collect::<Vec<String>>();
+    let missing = wanted.into_iter()
+        .filter(|package| !installed.iter().any(|dist| normalize(&dist.name) == normalize(&package.name) && dist.version == package.version))
+        .collect::<Vec<_>>();
+    (extras, missing)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn dist(name: &str, version: &str) -> Dist {
+        Dist { name: name.to_string(), version: version.to_string(), top_level: vec![], requires: vec![], info_dir: PathBuf::new() }
+    }
+
+    fn locked(name: &str, version: &str, groups: &[&str]) -> LockedPackage {
+        LockedPackage { name: name.to_string(), version: version.to_string(), sha256: None, groups: groups.iter().map(|group| group.to_string()).collect() }
+    }
+
+    #[test]
+    fn sync_installs_missing_and_removes_extras() {
+        let lock = Lock { python: None, packages: vec![locked("Requests", "2.31.0", &[]), locked("idna", "3.6", &[]), locked("pytest", "8.0.0", &["dev"])] };
+        let installed = [dist("requests", "2.30.0"), dist("idna", "3.6"), dist("flask", "3.0.0"), dist("pip", "24.0"), dist("pytest", "8.0.0")];
+
+        let (extras, missing) = changes(&lock, &[], &installed);
+        assert_eq!(extras, ["flask", "pytest"]);
+        assert_eq!(missing.iter().map(|package| package.name.as_str()).collect::<Vec<_>>(), ["Requests"]);
+
+        let (extras, missing) = changes(&lock, &[String::from("dev")], &installed);
+        assert_eq!(extras, ["flask"]);
+        assert_eq!(missing.len(), 1);
+    }
+
+    #[test]
+    fn sync_into_an_empty_venv_installs_the_selected_groups() {
+        let lock = Lock { python: None, packages: vec![locked("a", "1", &[]), locked("b", "1", &["docs"]), locked("c", "1", &["dev", "test"])] };
+        let (extras, missing) = changes(&lock, &[String::from("test")], &[]);
+        assert!(extras.is_empty());
+        assert_eq!(missing.iter().map(|package| package.name.as_str()).collect::<Vec<_>>(), ["a", "c"]);
+    }
+}
@@ -1,6 +1,6 @@
-use std::{path::{PathBuf, Path}, fs::{self, File}, process, env, result, io::{Write, Seek, Read, self}};
-use clap::{Parser, Subcommand};
-use pie::{Result, gitignore, input, run_cmd};
+use std::{path::{PathBuf, Path}, fs::{self, File}, env, io::{Write, Seek, Read, self}};
+use clap::Subcommand;
+use pie::{CmdOutcome, PieError, Result, gitignore, input, run_cmd};
 use spinach::{Spinach, Spinner};
 use ansi_term::Color;
 use random_string;
@@ -9,34 +9,10 @@ use remove_dir_all;
 use walkdir::{DirEntry, WalkDir};
 use zip::{write::FileOptions, result::ZipError};
 
-use crate::{config::{MainConfig, ProjectConfig}, commands::in_commands};
-
-pub fn is_in_proj(path: &PathBuf) -> Option<ProjectConfig>{
-    let project_conf = path.join("project.json");
-    if project_conf.exists(){
-        let project_conf_result: result::Result<ProjectConfig, serde_json::Error> = serde_json::from_str(&fs::read_to_string(project_conf).expect("Could not read 'project.json', please try again."));
-        if !project_conf_result.is_err() {
-            return Some(project_conf_result.unwrap());
-        }
-    }
-    None
-}
+use crate::{config::{MainConfig, ProjectConfig}, project::Project, commands::{in_commands, lock}};
 
 /////////////////////////////////////////////////////////////////////
 
-#[derive(Parser, Debug)]
-#[clap(
-    author = "Terroid", 
-    version = "0.0.1", 
-    about = "A Loose structured and easy to use python project manager.", 
-    long_about = "A Loose structured and easy to use python project manager. Different sets of commands can be accessed by using the cli inside a project directory or outside of it."
-    )
-]
-pub struct OutArgs {
-    #[clap(subcommand)]
-    pub command: OutSubCommands,
-}
-
 #[derive(Debug, Subcommand)]
 pub enum OutSubCommands{
 
@@ -46,9 +22,6 @@ pub enum OutSubCommands{
     /// Creates a new python project.
     New{ name: String, short_description: String },
 
-    /// List all the pie projects.
-    List,
-
     /// Deletes a project.
     #[clap(long_about="Deletes a project. The name of the command is long as to not delete the project on accident.")]
     DeleteProject{ name: String },
@@ -81,39 +54,46 @@ pub enum OutSubCommands{
 
 /////////////////////////////////////////////////////////////////////
 
-pub fn run_folder(path: &[String]) -> Result<()> {
+/// Runs a project folder or a python file, `path[0]`, with the rest of `path` as its arguments.
+pub fn exec(path: &[String], auto_venv: bool) -> Result<CmdOutcome> {
+    let target = Path::new(&path[0]);
+    if target.is_dir() {
+        run_folder(path, auto_venv)
+    } else if target.is_file() {
+        run_file(path)
+    } else if path[0].ends_with(".py") {
+        Err(PieError::Failed(format!("'{}' Python file does not exist.", path[0])))
+    } else {
+        Err(PieError::Failed(format!("'{}' is neither a project folder nor a python file.", path[0])))
+    }
+}
+
+pub fn run_folder(path: &[String], auto_venv: bool) -> Result<CmdOutcome> {
 
     //folder <args1> <args2>
     
     let path_buf = PathBuf::from(&path[0]);
-    let project_conf = is_in_proj(&path_buf);
-    if project_conf.is_none() {
-       println!("{}", format!("{}{}{}", Color::Red.paint("X |> '"), Color::Red.bold().paint(&path[0]), Color::Red.paint("' is not a valid pie project")));
-       process::exit(1);
-    }
+    let mut project = Project::open(&path_buf)?.ok_or(PieError::NotAProject(path_buf))?;
+    project.auto_venv = auto_venv;
 
-    env::set_current_dir(path_buf)?;
-
-    let conf = project_conf.unwrap();
-
-    in_commands::run((&path[1..]).to_vec(), conf)?;
-
-    Ok(())
+    in_commands::run(path[1..].to_vec(), &project)
 }
-pub fn run_file(path: &[String]) -> Result<()> {
+pub fn run_file(path: &[String]) -> Result<CmdOutcome> {
 
-    if !(&path[0]).ends_with(".py"){
-        println!("{}", format!("{}{}{}", Color::Red.paint("X |> '"), Color::Red.bold().paint(&path[0]), Color::Red.paint("' is not a valid .py script")));
-        return Ok(());
+    if !path[0].ends_with(".py"){
+        return Err(PieError::Failed(format!("'{}' is not a valid .py script", path[0])));
     }
 
     #[cfg(windows)]
-    run_cmd("python", &path.to_vec(), true, || {}, || {});
+    let outcome = run_cmd("python", path, true);
 
     #[cfg(not(windows))]
-    run_cmd("python3", &path.to_vec(), true, || {}, || {});
+    let outcome = run_cmd("python3", path, true);
 
-    Ok(())
+    match outcome {
+        CmdOutcome::SpawnFailed(e) => Err(PieError::MissingInterpreter(e.to_string())),
+        outcome => Ok(outcome),
+    }
 }
 
 fn zip_dir<T>(
@@ -146,7 +126,7 @@ where
             let mut f = File::open(path)?;
 
             f.read_to_end(&mut buffer)?;
-            zip.write_all(&*buffer)?;
+            zip.write_all(&buffer)?;
             buffer.clear();
         } else if !name.as_os_str().is_empty() {
             zip.add_directory(name.to_str().unwrap(), options)?;
@@ -156,9 +136,11 @@ where
     Ok(())
 }
 
+/// Zips `src_dir` into `dst_file`, leaving out the project's environment at `venv`.
 fn compress(
     src_dir: &str,
     dst_file: &str,
+    venv: &Path,
     method: zip::CompressionMethod,
 ) -> zip::result::ZipResult<()> {
     if !Path::new(src_dir).is_dir() {
@@ -166,20 +148,19 @@ fn compress(
     }
 
     let path = Path::new(dst_file);
-    let file = File::create(&path).unwrap();
+    let file = File::create(path)?;
 
     let walkdir = WalkDir::new(src_dir);
     let it = walkdir.into_iter();
 
-    zip_dir(&mut it.filter_map(|e| e.ok()), src_dir, file, method).unwrap();
-
-    Ok(())
+    let cwd = env::current_dir()?;
+    zip_dir(&mut it.filter_map(|e| e.ok()).filter(|e| !cwd.join(e.path()).starts_with(venv)), src_dir, file, method)
 }
 
 fn un_compress(filename: &str, dest_dir: &str) -> Result<()> {
     let fname = Path::new(&filename);
     let dest = Path::new(&dest_dir);
-    let file = File::open(&fname)?;
+    let file = File::open(fname)?;
 
     let mut archive = zip::ZipArchive::new(file)?;
 
@@ -188,7 +169,7 @@ fn un_compress(filename: &str, dest_dir: &str) -> Result<()> {
     for i in 0..archive.len() {
         let mut file = archive.by_index(i)?;
         let outpath = match file.enclosed_name() {
-            Some(path) => dest.join(path.to_owned()),
+            Some(path) => dest.join(path),
             None => continue,
         };
 
@@ -197,7 +178,7 @@ fn un_compress(filename: &str, dest_dir: &str) -> Result<()> {
         } else {
             if let Some(p) = outpath.parent() {
                 if !p.exists() {
-                    fs::create_dir_all(&p)?;
+                    fs::create_dir_all(p)?;
                 }
             }
             let mut outfile = fs::File::create(&outpath)?;
@@ -224,71 +205,65 @@ pub fn unpkg(project: &str, force: bool) -> Result<()> {
 
     if let Some(project_folder_dir) = project_folder {
         if project_folder_dir.1 != "pie"{
-            println!("{}", Color::Red.paint(format!("X |> Project '{}' is not a pie project.", project)));
-            process::exit(1);
+            return Err(PieError::Archive(format!("Project '{}' is not a pie project.", project)));
         }
 
         if !project_pie.exists() {
-            println!("{}", Color::Red.paint("X |> Project does not exist"));
-            process::exit(1);
+            return Err(PieError::Archive(format!("Project '{}' does not exist.", project)));
         }
 
         let project_dir_path  = Path::new(project_folder_dir.0);
 
-        if force{
+        if force && project_dir_path.exists() {
             remove_dir_all::remove_dir_all(project_dir_path)?;
         }
 
         if project_dir_path.is_dir() {
-            println!("{}", Color::Red.paint(format!("X |> Project Folder '{}' already exists, consider using the '--force' flag.", project_folder_dir.0)));
-            process::exit(1);
+            return Err(PieError::Failed(format!("Project Folder '{}' already exists, consider using the '--force' flag.", project_folder_dir.0)));
         }
 
         println!("{}", Color::Green.paint("|> Unpacking project."));
         let spinner = Spinner::new(vec!["-", "\\", "|", "/"], 130);
         let spinach = Spinach::new_with(spinner, Color::Yellow.paint("Packing project").to_string(), spinach::Color::Ignore);
-        let result = un_compress(project, project_folder_dir.0);
-        if result.is_err() {
+        if let Err(e) = un_compress(project, project_folder_dir.0) {
             spinach.stop_with("X |> ", Color::Red.paint("Could not unpackage project.").to_string(), spinach::Color::Ignore);
-            process::exit(1);
+            return Err(e);
         }
 
         spinach_log(&spinach, "Unpackaged Project.", "Initialising venv.", false);
 
-        let result = env::set_current_dir(&project_dir_path);
-        if result.is_err(){
+        if let Err(e) = env::set_current_dir(project_dir_path) {
             spinach.stop_with("X |> ", Color::Red.paint(format!("Cannot change directory into {}", project)).to_string(), spinach::Color::Ignore);
-            process::exit(1);
+            return Err(e.into());
         }
 
-        #[cfg(windows)]
-        run_cmd("python", &vec!["-m", "venv", "venv"], false, ||{
-            spinach_log(&spinach, "Could not find the 'python' command. Please check if python is installed, and if it is in your %PATH% environment variable", "Finalising Project Creation!", true);
-        }, ||{
-            spinach_log(&spinach, "Created a Virtual environment", "Finalising Project Creation!", false);
-        });
-
-        #[cfg(not(windows))]
-        run_cmd("python3", &vec!["-m", "venv", "venv"], false, ||{
-            spinach_log(&spinach, "Could not find the 'python3' command. Please check if python is installed, and if it is in your %PATH% environment variable", "Finalising Project Creation!", true);
-        }, ||{
-            spinach_log(&spinach, "Created a Virtual environment", "Finalising Project Creation!", false);
-        });
-
-        let result = in_commands::reqs(true, false, None);
-        if result.is_err(){
-            spinach.stop_with("X |> ", Color::Red.paint("Cannot install requirements from 'requirements.txt'").to_string(), spinach::Color::Ignore);
-            process::exit(1);
+        let unpacked = match Project::load(Path::new(".")) {
+            Ok(unpacked) => unpacked,
+            Err(e) => {
+                spinach.stop_with("X |> ", Color::Red.paint("Cannot read the unpackaged 'project.json'").to_string(), spinach::Color::Ignore);
+                return Err(e);
+            }
+        };
+        create_env(&spinach, &unpacked);
+
+        // A locked project gets the exact versions its author had.
+        let (installed, source) = if unpacked.lock_file().exists() {
+            (lock::sync(&unpacked, &[]), "pie.lock")
+        } else {
+            (in_commands::reqs(true, false, false, &unpacked), "requirements.txt")
+        };
+        if let Err(e) = installed {
+            spinach.stop_with("X |> ", Color::Red.paint(format!("Cannot install requirements from '{}'", source)).to_string(), spinach::Color::Ignore);
+            return Err(e);
         }
 
-        spinach_log(&spinach, "Installed requirements from 'requirements.txt'", "Finalising unpackaging", false);
+        spinach_log(&spinach, &format!("Installed requirements from '{}'", source), "Finalising unpackaging", false);
 
         spinach.stop_with("√ |>", Color::Green.bold().paint(format!("Project '{}' successfully unpackaged!", project)).to_string(), spinach::Color::Ignore);
 
 
     } else {
-        println!("{}", Color::Red.paint(format!("X |> '{}' is a folder. Please provide a valid '.pie' project.", project)));
-        process::exit(1);
+        return Err(PieError::Archive(format!("'{}' is a folder. Please provide a valid '.pie' project.", project)));
     }
 
     Ok(())
@@ -299,41 +274,29 @@ pub fn pkg(project : &str, force: bool) -> Result<()> {
     let path_str = project.to_string() + ".pie";
     let project_pie = Path::new(&path_str);
 
-    if !Path::new(project).is_dir(){
-        println!("{}", Color::Red.paint(format!("X |> Project folder '{}' not found!", project)));
-        process::exit(1);
-    }
+    let pie_project = Project::open(Path::new(project))?.ok_or_else(|| PieError::NotAProject(PathBuf::from(project)))?;
 
-    if force {
-        if project_pie.exists() {
-            fs::remove_file(project_pie).expect("Could not delete 'project.pie'");
+    if force
+        && project_pie.exists() {
+            fs::remove_file(project_pie)?;
         }
-    }
 
     if project_pie.exists() {
-        println!("{}", Color::Red.paint(format!("X |> Project '{}' already exists in this directory, consider using the '--force' flag.", path_str)));
-        process::exit(1);
+        return Err(PieError::Failed(format!("Project '{}' already exists in this directory, consider using the '--force' flag.", path_str)));
     }
 
     println!("{}", Color::Green.paint("|> Packing project."));
     let spinner = Spinner::new(vec!["-", "\\", "|", "/"], 130);
     let spinach = Spinach::new_with(spinner, Color::Yellow.paint("Packing project").to_string(), spinach::Color::Ignore);
 
-    let mut err = false;
-
-    // Go inside project directory, and note down the requirements.
-    env::set_current_dir(project)?;
-    in_commands::reqs(false, false, None)?;
-    env::set_current_dir("..")?;
-
-    match compress(project, env::current_dir()?.join(format!("{}.pie", project)).to_str().unwrap(), zip::CompressionMethod::Stored){
-        Err(_) => {err = true;},
-        _ => {}
+    // Note down the requirements.
+    if in_commands::reqs(false, false, false, &pie_project).is_err() {
+        spinach_log(&spinach, "Could not update 'requirements.txt', packaging it as it is.", "Packing project", true);
     }
 
-    if err{
+    if let Err(e) = compress(project, env::current_dir()?.join(format!("{}.pie", project)).to_str().unwrap(), &pie_project.venv(), zip::CompressionMethod::Stored) {
         spinach.stop_with("X |> ", Color::Red.paint("Could not package project.").to_string(), spinach::Color::Ignore);
-        process::exit(1);
+        return Err(e.into());
     }
     spinach.stop_with("√ |>", Color::Green.bold().paint(format!("Project '{}' successfully packaged!", project)).to_string(), spinach::Color::Ignore);
     Ok(())
@@ -343,30 +306,29 @@ pub fn delete_project(name: &str) -> Result<()> {
 
     let proj_dir = env::current_dir()?.join(name);
 
-    if let Some(_project_conf) = is_in_proj(&proj_dir) {
+    if let Some(project) = Project::open(&proj_dir)? {
         let captcha = name.to_string().to_uppercase() + "-" + &random_string::generate(5, "ABCDEFGHIJKLMNOPQRSTUVWXYZ");
         println!("{}{}", Color::Red.paint("|> Please type this captcha to confirm project deletion: "), Color::Green.paint(&captcha));
         let input_captcha = input(Color::Green.paint("|> Enter captcha: ").to_string())?;
         if input_captcha.to_uppercase() == captcha{
             
-            let result = remove_dir_all::remove_dir_all(proj_dir);
-            if result.is_err(){
-                println!("{}", Color::Red.paint("X |> There was an issue while removing the directories. Please try again."));
-                return Ok(());
+            remove_dir_all::remove_dir_all(&proj_dir)?;
+            // An environment kept outside the project, e.g. in the central store, goes too.
+            let venv = project.venv();
+            if venv.is_dir() && !venv.starts_with(&proj_dir) {
+                remove_dir_all::remove_dir_all(venv)?;
             }
             println!("{}", Color::Green.paint("√ |> Project successfully deleted."));
             return Ok(());
 
         } else {
             
-            println!("{}", Color::Red.paint("X |> Wrong Captcha. Project deletion, aborted."));
+            return Err(PieError::Failed(String::from("Wrong Captcha. Project deletion, aborted.")));
 
         }
     }
 
-    println!("{}", Color::Red.paint("X |> The given folder is not a pie-project."));
-
-    Ok(())
+    Err(PieError::NotAProject(proj_dir))
 }
 
 pub fn list() -> Result<()> {
@@ -376,17 +338,13 @@ pub fn list() -> Result<()> {
     let mut projs = vec![];
 
     for path in paths {
-        let path = path.unwrap().path();
-        if path.is_dir() && path.join("project.json").exists() {
-            let project_conf_result: result::Result<ProjectConfig, serde_json::Error> = serde_json::from_str(&fs::read_to_string(path.join("project.json")).expect("Could not read 'project.json', please try again."));
-            if !project_conf_result.is_err() {
-                projs.push(path.file_name()
-                        .unwrap()
-                        .to_str()
-                        .unwrap()
-                        .to_owned()
-                    );
-            }
+        let path = path?.path();
+        if path.is_dir() && matches!(Project::open(&path), Ok(Some(_))) {
+            projs.push(path.file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .into_owned()
+                );
         }
     }
 
@@ -395,14 +353,14 @@ pub fn list() -> Result<()> {
     Ok(())
 }
 
-pub fn config<T>(config_loc: &PathBuf, func: &T) -> Result<()>
+pub fn config<T>(config_loc: &Path, func: &T) -> Result<()>
 where
-    T: Fn(&PathBuf) -> Result<()>
+    T: Fn(&Path) -> Result<bool>
 {
     if config_loc.exists() {
-        fs::remove_file(&config_loc).unwrap();
+        fs::remove_file(config_loc)?;
     }
-    func(&config_loc).unwrap();
+    func(config_loc)?;
     Ok(())
 }
 
@@ -414,51 +372,51 @@ fn spinach_log(spinach: &Spinach, frozen_msg: &str, new_message: &str, err: bool
     }
 }
 
+/// Creates the project's environment with its backend. A failure is logged, not returned:
+/// the project is usable anyway, and the environment gets created when it is first needed.
+fn create_env(spinach: &Spinach, project: &Project) {
+    match project.env.create(&project.venv(), project.python_pin().as_ref()) {
+        Ok(()) => spinach_log(spinach, &format!("Created a Virtual environment with {}", project.env.name()), "Finalising Project Creation!", false),
+        Err(e) => spinach_log(spinach, &e.to_string(), "Finalising Project Creation!", true),
+    }
+}
+
 pub fn new(name: &str, description: &str) -> Result<()> {
     
     let relative_path = Path::new(name);
     if relative_path.is_dir() {
-        println!("{}", Color::Red.paint("X |> A Folder with that name already exists"));
-        process::exit(0);
+        return Err(PieError::Failed(format!("A Folder named '{}' already exists", name)));
     }
 
     println!("{}", Color::Green.paint("|> Creating Project..."));
     let spinner = Spinner::new(vec!["-", "\\", "|", "/"], 130);
     let spinach = Spinach::new_with(spinner, "Creating Project", spinach::Color::Ignore);
 
-    let result = create_project_files(relative_path, name, description);
-    if result.is_err(){
+    if let Err(e) = create_project_files(relative_path, name, description) {
         spinach.stop_with("X |> ", Color::Red.paint("Could not create project files.").to_string(), spinach::Color::Ignore);
-        process::exit(1);
+        return Err(e);
     }
 
     spinach_log(&spinach, "Created project files!", "Creating local git repo!", false);
 
-    let result = env::set_current_dir(&relative_path);
-    if result.is_err(){
+    if let Err(e) = env::set_current_dir(relative_path) {
         spinach.stop_with("X |> ", Color::Red.paint(format!("Cannot change directory into {}", name)).to_string(), spinach::Color::Ignore);
-        process::exit(1);
+        return Err(e.into());
     }
 
-    run_cmd("git", &vec!["init"], false, ||{
-        spinach_log(&spinach, "Could not find the 'git' command.", "Creating virtual env!", true);
-    }, ||{
+    if run_cmd("git", &["init"], false).success() {
         spinach_log(&spinach, "Initialised a local Git repo!", "Creating virtual env!", false);
-    });
-
-    #[cfg(windows)]
-    run_cmd("python", &vec!["-m", "venv", "venv"], false, ||{
-        spinach_log(&spinach, "Could not find the 'python' command. Please check if python is installed, and if it is in your %PATH% environment variable", "Finalising Project Creation!", true);
-    }, ||{
-        spinach_log(&spinach, "Created a Virtual environment", "Finalising Project Creation!", false);
-    });
+    } else {
+        spinach_log(&spinach, "Could not find the 'git' command.", "Creating virtual env!", true);
+    }
 
-    #[cfg(not(windows))]
-    run_cmd("python3", &vec!["-m", "venv", "venv"], false, ||{
-        spinach_log(&spinach, "Could not find the 'python3' command. Please check if python is installed, and if it is in your %PATH% environment variable", "Finalising Project Creation!", true);
-    }, ||{
-        spinach_log(&spinach, "Created a Virtual environment", "Finalising Project Creation!", false);
-    });
+    match Project::load(Path::new(".")) {
+        Ok(project) => create_env(&spinach, &project),
+        Err(e) => {
+            spinach.stop_with("X |> ", Color::Red.paint("Cannot read the new 'project.json'").to_string(), spinach::Color::Ignore);
+            return Err(e);
+        }
+    }
 
     spinach.stop_with("√ |>", Color::Green.bold().paint(format!("Project '{}' successfully created!", name)).to_string(), spinach::Color::Ignore);
 
@@ -471,9 +429,9 @@ fn create_project_files(relative_path: &Path, name: &str, description: &str) ->
     fs::write(relative_path.join("README.md"), format!("# {}\n\n{}", name, description))?;
     fs::write(relative_path.join(".gitignore"), gitignore())?;
     fs::write(relative_path.join("requirements.txt"), "")?;
-    let config = MainConfig::from_file().expect(&Color::Red.paint("Could not read main config file. Please run the 'cfg' command to rewrite the config file.").to_string());
+    let config = MainConfig::from_file()?;
     fs::write(relative_path.join("project.json"), 
-    serde_json::to_string_pretty(&ProjectConfig::new(name, description, &config))?)?;
+    serde_json::to_string_pretty(&ProjectConfig::new(name, description, &config)).map_err(|e| PieError::Failed(e.to_string()))?)?;
 
     Ok(())
 }
\ No newline at end of file
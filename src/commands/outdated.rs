@@ -0,0 +1,171 @@
+use std::{collections::BTreeMap, fs};
+
+use ansi_term::Color;
+use pie::{PieError, Result};
+use serde::Serialize;
+
+use crate::{config::MainConfig, project::Project, python::{dists::{self, normalize, Dist}, index::Index, lock::is_locked, pep440::{Specifiers, Version}, requirements}};
+
+use super::in_commands::ensure_venv;
+
+#[derive(Serialize)]
+struct Outdated {
+    name: String,
+    current: String,
+    /// The newest version requirements.txt allows, if any does.
+    wanted: Option<String>,
+    latest: String,
+}
+
+/// Lists the packages in the venv that have a newer version on the index, `index` or else the
+/// `index_url` of pie's config. With `upgrade`, requirements.txt's `==` pins are moved to the latest versions.
+pub fn outdated(index: Option<String>, json: bool, upgrade: bool, project: &Project) -> Result<()> {
+    ensure_venv(project)?;
+
+    let index = Index::new(&match index {
+        Some(index) => index,
+        None => MainConfig::from_file()?.index_url,
+    });
+    let installed = dists::installed(&project.venv()).into_iter().filter(|dist| is_locked(&dist.name)).collect::<Vec<_>>();
+    let names = installed.iter().map(|dist| dist.name.to_owned()).collect::<Vec<String>>();
+    let available = index.versions(&names, &project.python())?;
+
+    let requirements_txt = fs::read_to_string(project.requirements()).unwrap_or_default();
+    let (rows, unknown) = compare(&installed, &available, &requirements_txt);
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&rows).map_err(|e| PieError::Failed(e.to_string()))?);
+        return Ok(());
+    }
+
+    if !unknown.is_empty() {
+        println!("{}", Color::Yellow.paint(format!("? |> Not found on the index: {}", unknown.join(", "))));
+    }
+    if rows.is_empty() {
+        println!("{}", Color::Green.paint("√ |> Every package is up to date."));
+        return Ok(());
+    }
+    print_table(&rows);
+
+    if upgrade {
+        let latest = rows.iter().map(|row| (normalize(&row.name), row.latest.to_owned())).collect();
+        let (text, changed) = requirements::upgrade_pins(&requirements_txt, &latest);
+        if changed.is_empty() {
+            println!("{}", Color::Green.paint("|> No pins in 'requirements.txt' to upgrade."));
+            return Ok(());
+        }
+
+        fs::write(project.requirements(), text)?;
+        for (old, new) in &changed {
+            println!("{}", Color::Green.paint(format!("~ {} -> {}", old, new)));
+        }
+        println!("{}", Color::Green.bold().paint(format!("√ |> Upgraded {} pins in 'requirements.txt'. Run 'pie reqs --install' to install them.", changed.len())));
+    }
+
+    Ok(())
+}
+
+/// The installed distributions with a newer version in `available`, and the names of those that have
+/// no version there at all. `wanted` is the newest version the specifiers in `requirements_txt` allow.
+fn compare(installed: &[Dist], available: &BTreeMap<String, Vec<Version>>, requirements_txt: &str) -> (Vec<Outdated>, Vec<String>) {
+    let specifiers = requirements_txt.lines()
+        .filter_map(requirements::requirement_specifiers)
+        .filter_map(|(name, specifiers)| Some((normalize(&name), specifiers.parse::<Specifiers>().ok()?)))
+        .collect::<BTreeMap<String, Specifiers>>();
+
+    let mut rows = vec![];
+    let mut unknown = vec![];
+    for dist in installed {
+        let current = match dist.version.parse::<Version>() {
+            Ok(current) => current,
+            Err(_) => continue,
+        };
+        let specifiers = specifiers.get(&normalize(&dist.name)).cloned().unwrap_or_default();
+        // Pre-releases are only offered to those already on one, or asking for one.
+        let candidates = available.get(&dist.name).into_iter().flatten()
+            .filter(|version| !version.is_prerelease() || current.is_prerelease() || specifiers.allows_prereleases())
+            .collect::<Vec<&Version>>();
+
+        let latest = match candidates.iter().max() {
+            Some(latest) => latest,
+            None => { unknown.push(dist.name.to_owned()); continue; },
+        };
+        if **latest <= current {
+            continue;
+        }
+        rows.push(Outdated {
+            name: dist.name.to_owned(),
+            current: dist.version.to_owned(),
+            wanted: candidates.iter().filter(|version| specifiers.contains(version)).max().map(|wanted| wanted.to_string()),
+            latest: latest.to_string(),
+        });
+    }
+    (rows, unknown)
+}
+
+fn print_table(rows: &[Outdated]) {
+    let header = ["Package", "Current", "Wanted", "Latest"];
+    let cells = rows.iter()
+        .map(|row| [row.name.to_owned(), row.current.to_owned(), row.wanted.clone().unwrap_or_else(|| String::from("-")), row.latest.to_owned()])
+        .collect::<Vec<[String; 4]>>();
+    let widths = (0..3).map(|i| cells.iter().map(|row| row[i].len()).chain([header[i].len()]).max().unwrap_or(0)).collect::<Vec<usize>>();
+
+    println!("{}", Color::Green.bold().paint(format!("{:<w0$}  {:<w1$}  {:<w2$}  {}", header[0], header[1], header[2], header[3], w0 = widths[0], w1 = widths[1], w2 = widths[2])));
+    for row in cells {
+        println!("{:<w0$}  {:<w1$}  {:<w2$}  {}", row[0], row[1], row[2], row[3], w0 = widths[0], w1 = widths[1], w2 = widths[2]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn dist(name: &str, version: &str) -> Dist {
+        Dist { name: name.to_string(), version: version.to_string(), top_level: vec![], requires: vec![], info_dir: PathBuf::new() }
+    }
+
+    fn versions(names: &[(&str, &[&str])]) -> BTreeMap<String, Vec<Version>> {
+        names.iter().map(|(name, versions)| (name.to_string(), versions.iter().map(|version| version.parse().unwrap()).collect())).collect()
+    }
+
+    #[test]
+    fn wanted_is_the_newest_version_requirements_allow() {
+        let installed = [dist("Flask", "2.0.1"), dist("numpy", "1.26.0"), dist("requests", "2.31.0"), dist("PyYAML", "6.0.1"), dist("left-pad", "1.0")];
+        let available = versions(&[
+            ("Flask", &["2.0.1", "2.3.3", "3.0.0"]),
+            ("numpy", &["1.26.0", "1.26.4", "2.0.0", "2.1.0rc1"]),
+            ("requests", &["2.31.0", "2.32.0"]),
+            ("PyYAML", &["6.0.1"]),
+        ]);
+        let requirements_txt = "flask[async]>=2,<3  # web\nnumpy~=1.26.0\n./vendor/requests#egg=requests\n";
+
+        let (rows, unknown) = compare(&installed, &available, requirements_txt);
+        let rows = rows.iter().map(|row| (row.name.as_str(), row.current.as_str(), row.wanted.as_deref(), row.latest.as_str())).collect::<Vec<_>>();
+        assert_eq!(rows, [
+            ("Flask", "2.0.1", Some("2.3.3"), "3.0.0"),
+            ("numpy", "1.26.0", Some("1.26.4"), "2.0.0"),
+            ("requests", "2.31.0", Some("2.32.0"), "2.32.0"),
+        ]);
+        assert_eq!(unknown, ["left-pad"]);
+    }
+
+    #[test]
+    fn nothing_wanted_when_requirements_allow_no_candidate() {
+        let (rows, _) = compare(&[dist("Django", "4.2.0")], &versions(&[("Django", &["4.2.0", "5.0.0"])]), "django==4.2.0\n");
+        assert_eq!(rows.iter().map(|row| (row.wanted.as_deref(), row.latest.as_str())).collect::<Vec<_>>(), [(Some("4.2.0"), "5.0.0")]);
+
+        let (rows, _) = compare(&[dist("Django", "4.2.0")], &versions(&[("Django", &["4.2.0", "5.0.0"])]), "django<4\n");
+        assert_eq!(rows.iter().map(|row| row.wanted.as_deref()).collect::<Vec<_>>(), [None]);
+    }
+
+    #[test]
+    fn pre_releases_only_for_those_on_one() {
+        let available = versions(&[("numpy", &["1.26.0", "2.0.0rc1"])]);
+        assert!(compare(&[dist("numpy", "1.26.0")], &available, "").0.is_empty());
+
+        let (rows, _) = compare(&[dist("numpy", "2.0.0b1")], &available, "");
+        assert_eq!(rows.iter().map(|row| row.latest.as_str()).collect::<Vec<_>>(), ["2.0.0rc1"]);
+    }
+}
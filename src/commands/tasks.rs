@@ -0,0 +1,182 @@
+use std::{collections::BTreeMap, io, process::Command, result};
+
+use ansi_term::Color;
+use pie::{CmdOutcome, PieError, Result, run_command};
+
+use crate::{config::Script, project::Project};
+
+use super::in_commands::ensure_venv;
+
+/// Runs a task from the project's `scripts` section, after its dependencies.
+/// `args` are appended to the named task's own command only.
+pub fn task(name: Option<String>, list: bool, args: Vec<String>, project: &Project) -> Result<CmdOutcome> {
+
+    let empty = BTreeMap::new();
+    let scripts = project.config.scripts.as_ref().unwrap_or(&empty);
+
+    let name = match name {
+        Some(name) if !list => name,
+        _ => {
+            list_tasks(scripts)?;
+            return Ok(CmdOutcome::Exited(0));
+        }
+    };
+
+    let mut order = vec![];
+    resolve(&name, scripts, &mut vec![], &mut order).map_err(PieError::Failed)?;
+
+    ensure_venv(project)?;
+
+    for task in order {
+        let cmd = match &scripts[task] {
+            Script::Cmd(cmd) | Script::Full { cmd: Some(cmd), .. } => cmd,
+            _ => continue,
+        };
+        let cmd = if task == name && !args.is_empty() {
+            format!("{} {}", cmd, args.iter().map(|arg| shell_quote(arg)).collect::<Vec<String>>().join(" "))
+        } else {
+            cmd.to_owned()
+        };
+
+        println!("{}{}{}", Color::Green.paint("|> "), Color::Green.bold().paint(task), Color::Yellow.paint(format!(": {}", cmd)));
+        let outcome = run_shell(&cmd, project).spawned(if cfg!(windows) {"cmd"} else {"sh"})?;
+        if !outcome.success() {
+            println!("{}", Color::Red.paint(format!("X |> Task '{}' failed with exit code {}.", task, outcome.exit_code())));
+            return Ok(outcome);
+        }
+    }
+
+    Ok(CmdOutcome::Exited(0))
+}
+
+fn list_tasks(scripts: &BTreeMap<String, Script>) -> Result<()> {
+    if scripts.is_empty() {
+        return Err(PieError::Failed(String::from("This project.json doesn't contain a 'scripts' field.")));
+    }
+
+    println!("{}", Color::Green.paint("|> Tasks in this project:"));
+    for (name, script) in scripts {
+        let description = match script {
+            Script::Cmd(cmd) => cmd.to_owned(),
+            Script::Deps(deps) => format!("[{}]", deps.join(", ")),
+            Script::Full { deps, cmd } => match cmd {
+                Some(cmd) if deps.is_empty() => cmd.to_owned(),
+                Some(cmd) => format!("[{}] then {}", deps.join(", "), cmd),
+                None => format!("[{}]", deps.join(", ")),
+            },
+        };
+        println!("   {}: {}", Color::Green.bold().paint(name), description);
+    }
+    Ok(())
+}
+
+/// Depth first walk that appends every task after its dependencies, once.
+fn resolve<'a>(name: &'a str, scripts: &'a BTreeMap<String, Script>, stack: &mut Vec<&'a str>, order: &mut Vec<&'a str>) -> result::Result<(), String> {
+    if order.contains(&name) {
+        return Ok(());
+    }
+    if stack.contains(&name) {
+        stack.push(name);
+        return Err(format!("Tasks depend on each other in a cycle: {}", stack.join(" -> ")));
+    }
+
+    let (key, script) = scripts.get_key_value(name).ok_or_else(|| match stack.last() {
+        Some(parent) => format!("Task '{}' depends on unknown task '{}'.", parent, name),
+        None => format!("No task named '{}'. Use 'pie task --list' to see all tasks.", name),
+    })?;
+
+    let deps = match script {
+        Script::Cmd(_) => &[][..],
+        Script::Deps(deps) | Script::Full { deps, .. } => deps,
+    };
+
+    stack.push(key);
+    for dep in deps {
+        resolve(dep, scripts, stack, order)?;
+    }
+    stack.pop();
+
+    order.push(key);
+    Ok(())
+}
+
+/// Runs `cmd` in the project root, inside the activated environment.
+fn run_shell(cmd: &str, project: &Project) -> CmdOutcome {
+    let mut command = if cfg!(windows) {
+        let mut command = Command::new("cmd");
+        command.args(["/C", cmd]);
+        command
+    } else {
+        let mut command = Command::new("sh");
+        command.args(["-c", cmd]);
+        command
+    };
+
+    command.current_dir(&project.root);
+    if let Err(e) = project.env.activate(&project.venv(), &mut command) {
+        return CmdOutcome::SpawnFailed(io::Error::new(io::ErrorKind::InvalidInput, e.to_string()));
+    }
+
+    run_command(&mut command)
+}
+
+fn shell_quote(arg: &str) -> String {
+    if !arg.is_empty() && arg.chars().all(|c| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c)) {
+        return arg.to_string();
+    }
+    if cfg!(windows) {
+        format!("\"{}\"", arg.replace('"', "\\\""))
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scripts(json: &str) -> BTreeMap<String, Script> {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn order<'a>(name: &'a str, scripts: &'a BTreeMap<String, Script>) -> result::Result<Vec<&'a str>, String> {
+        let mut order = vec![];
+        resolve(name, scripts, &mut vec![], &mut order)?;
+        Ok(order)
+    }
+
+    #[test]
+    fn dependencies_run_first_and_once() {
+        let scripts = scripts(r#"{"ci": ["lint", "test"], "lint": "ruff .", "test": {"deps": ["build", "lint"], "cmd": "pytest"}, "build": "make"}"#);
+        assert_eq!(order("ci", &scripts).unwrap(), ["lint", "build", "test", "ci"]);
+        assert_eq!(order("lint", &scripts).unwrap(), ["lint"]);
+    }
+
+    #[test]
+    fn cycles_are_reported() {
+        let scripts = scripts(r#"{"a": ["b"], "b": {"deps": ["a"], "cmd": "x"}, "c": ["c"]}"#);
+        assert_eq!(order("a", &scripts).unwrap_err(), "Tasks depend on each other in a cycle: a -> b -> a");
+        assert_eq!(order("c", &scripts).unwrap_err(), "Tasks depend on each other in a cycle: c -> c");
+    }
+
+    #[test]
+    fn unknown_tasks_are_reported() {
+        let scripts = scripts(r#"{"a": ["missing"]}"#);
+        assert_eq!(order("a", &scripts).unwrap_err(), "Task 'a' depends on unknown task 'missing'.");
+        assert!(order("nope", &scripts).unwrap_err().starts_with("No task named 'nope'."));
+    }
+
+    #[test]
+    fn arguments_are_quoted_for_the_shell() {
+        assert_eq!(shell_quote("-k=test_x"), "-k=test_x");
+        if cfg!(windows) {
+            assert_eq!(shell_quote("two words"), "\"two words\"");
+            assert_eq!(shell_quote("say \"hi\""), "\"say \\\"hi\\\"\"");
+        } else {
+            assert_eq!(shell_quote("two words"), "'two words'");
+            assert_eq!(shell_quote("it's"), "'it'\\''s'");
+            assert_eq!(shell_quote(""), "''");
+            assert_eq!(shell_quote("$HOME;rm"), "'$HOME;rm'");
+        }
+    }
+}
@@ -0,0 +1,393 @@
+use std::{collections::{BTreeMap, BTreeSet}, fs};
+
+use ansi_term::Color;
+use clap::ArgEnum;
+use pie::{PieError, Result};
+use serde::Serialize;
+
+use crate::{project::Project, python::{dists::{self, normalize, Dist}, lock::is_locked, pep440::{Specifiers, Version}, requirements}};
+
+use super::in_commands::ensure_venv;
+
+#[derive(Debug, Clone, Copy, ArgEnum)]
+pub enum TreeFormat {
+    Text,
+    Json,
+    Dot,
+}
+
+/// A package in the tree. Forward trees fill `dependencies`, reverse ones `required_by`.
+#[derive(Serialize, Clone)]
+struct Node {
+    name: String,
+    version: Option<String>,
+    /// What its parent in the tree requires of it, e.g. `>=2.0`.
+    #[serde(skip_serializing_if = "String::is_empty")]
+    required: String,
+    top_level: bool,
+    #[serde(skip_serializing_if = "is_false")]
+    conflict: bool,
+    #[serde(skip_serializing_if = "is_false")]
+    cycle: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    dependencies: Vec<Node>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    required_by: Vec<Node>,
+}
+
+fn is_false(b: &bool) -> bool {
+    !b
+}
+
+fn has_cycle(node: &Node) -> bool {
+    node.cycle || node.dependencies.iter().chain(&node.required_by).any(has_cycle)
+}
+
+/// The installed distributions by normalized name, and the ones requirements.txt asks for.
+struct Graph {
+    dists: BTreeMap<String, Dist>,
+    top_level: BTreeSet<String>,
+}
+
+    impl Graph {
+        fn node(&self, name: &str, required: &str) -> Node {
+            let dist = self.dists.get(name);
+            Node {
+                name: dist.map_or_else(|| name.to_string(), |dist| dist.name.to_owned()),
+                version: dist.map(|dist| dist.version.to_owned()),
+                required: required.to_string(),
+                top_level: self.top_level.contains(name),
+                conflict: dist.is_some_and(|dist| !satisfies(&dist.version, required)),
+                cycle: false,
+                dependencies: vec![],
+                required_by: vec![],
+            }
+        }
+
+        /// `name` and what it depends on. A dependency limited by a marker that isn't installed was
+        /// left out for this platform, so it isn't shown. `path` holds the packages above it, to catch cycles.
+        /// A package reached again through another branch, or from another root, reuses the tree `built`
+        /// for it, unless that tree ran into a cycle, which depends on the path taken to it.
+        fn dependencies(&self, name: &str, required: &str, path: &mut Vec<String>, cycles: &mut BTreeSet<Vec<String>>, built: &mut BTreeMap<String, Node>) -> Node {
+            if let Some(node) = built.get(name) {
+                // The requirement is on the edge, so it is checked against the package it is about.
+                let mut node = node.clone();
+                node.required = required.to_string();
+                node.conflict = self.dists.get(name).is_some_and(|dist| !satisfies(&dist.version, required));
+                return node;
+            }
+
+            let mut node = self.node(name, required);
+            if let Some(start) = path.iter().position(|above| above == name) {
+                cycles.insert(cycle(&path[start..]));
+                node.cycle = true;
+                return node;
+            }
+
+            if let Some(dist) = self.dists.get(name) {
+                path.push(name.to_string());
+                node.dependencies = dist.requires.iter()
+                    .filter(|dependency| !dependency.conditional || self.dists.contains_key(&dependency.name))
+                    .map(|dependency| self.dependencies(&dependency.name, &dependency.specifiers, path, cycles, built))
+                    .collect();
+                path.pop();
+            }
+
+            if !has_cycle(&node) {
+                built.insert(name.to_string(), node.clone());
+            }
+            node
+        }
+
+        /// `name` and the packages that depend on it, up to those nothing depends on. A package reached
+        /// again through another branch reuses the tree `built` for it, unless that tree ran into a cycle,
+        /// which depends on the path taken to it.
+        fn dependents(&self, name: &str, path: &mut Vec<String>, cycles: &mut BTreeSet<Vec<String>>, built: &mut BTreeMap<String, Node>) -> Node {
+            if let Some(node) = built.get(name) {
+                return node.clone();
+            }
+
+            let mut node = self.node(name, "");
+            if let Some(start) = path.iter().position(|below| below == name) {
+                cycles.insert(cycle(&path[start..].iter().rev().cloned().collect::<Vec<String>>()));
+                node.cycle = true;
+                return node;
+            }
+
+            path.push(name.to_string());
+            for (dependent, dist) in &self.dists {
+                for dependency in dist.requires.iter().filter(|dependency| dependency.name == name) {
+                    let mut parent = self.dependents(dependent, path, cycles, built);
+                    // The requirement is on the edge, so it is checked against the package it is about.
+                    parent.required = dependency.specifiers.to_owned();
+                    parent.conflict = self.dists.get(name).is_some_and(|dist| !satisfies(&dist.version, &dependency.specifiers));
+                    node.required_by.push(parent);
+                }
+            }
+            path.pop();
+
+            if !has_cycle(&node) {
+                built.insert(name.to_string(), node.clone());
+            }
+            node
+        }
+
+        /// Packages nothing else depends on, and those requirements.txt asks for. Packages in a cycle
+        /// that none of those reach would be left out, so one package of each such cycle is added.
+        fn roots(&self) -> Vec<String> {
+            let required = self.dists.values().flat_map(|dist| dist.requires.iter().map(|dependency| dependency.name.to_owned())).collect::<BTreeSet<String>>();
+            let mut roots = self.dists.keys()
+                .filter(|name| !required.contains(*name) && is_locked(name))
+                .chain(&self.top_level)
+                .cloned()
+                .collect::<BTreeSet<String>>();
+
+            let mut reached = roots.iter().flat_map(|root| self.reachable(root)).chain(roots.iter().cloned()).collect::<BTreeSet<String>>();
+            for name in self.dists.keys().filter(|name| is_locked(name)) {
+                if reached.contains(name) {
+                    continue;
+                }
+                let reachable = self.reachable(name);
+                if reachable.contains(name) {
+                    roots.insert(name.to_owned());
+                    reached.extend(reachable);
+                }
+            }
+            roots.into_iter().collect()
+        }
+
+        /// The installed packages `name` depends on, directly or not. It is among them if it is in a cycle.
+        fn reachable(&self, name: &str) -> BTreeSet<String> {
+            let mut reached = BTreeSet::new();
+            let mut stack = vec![name.to_string()];
+            while let Some(name) = stack.pop() {
+                for dependency in self.dists.get(&name).map_or(&[][..], |dist| &dist.requires) {
+                    if self.dists.contains_key(&dependency.name) && reached.insert(dependency.name.to_owned()) {
+                        stack.push(dependency.name.to_owned());
+                    }
+                }
+            }
+            reached
+        }
+
+        /// Every installed requirement whose installed version doesn't satisfy it.
+        fn conflicts(&self) -> Vec<String> {
+            let mut conflicts = vec![];
+            for dist in self.dists.values() {
+                for dependency in &dist.requires {
+                    match self.dists.get(&dependency.name) {
+                        Some(required) if !satisfies(&required.version, &dependency.specifiers) => conflicts.push(format!(
+                            "{} {} requires {}{}, but {} is installed.", dist.name, dist.version, required.name, dependency.specifiers, required.version)),
+                        None if !dependency.conditional => conflicts.push(format!(
+                            "{} {} requires {}{}, which isn't installed.", dist.name, dist.version, dependency.name, dependency.specifiers)),
+                        _ => {}
+                    }
+                }
+            }
+            conflicts
+        }
+    }
+
+/// Shows the dependency graph of the venv, read from the metadata of the installed packages.
+/// With `reverse`, shows what depends on that package instead.
+pub fn tree(reverse: Option<String>, format: TreeFormat, project: &Project) -> Result<()> {
+    ensure_venv(project)?;
+
+    let requirements_txt = fs::read_to_string(project.requirements()).unwrap_or_default();
+    let graph = Graph {
+        dists: dists::installed(&project.venv()).into_iter().map(|dist| (normalize(&dist.name), dist)).collect(),
+        top_level: requirements_txt.lines().filter_map(requirements::requirement_name).map(|name| normalize(&name)).collect(),
+    };
+
+    let mut cycles = BTreeSet::new();
+    let nodes = match &reverse {
+        Some(name) => {
+            let name = normalize(name);
+            if !graph.dists.contains_key(&name) {
+                return Err(PieError::Failed(format!("'{}' isn't installed in the venv.", name)));
+            }
+            vec![graph.dependents(&name, &mut vec![], &mut cycles, &mut BTreeMap::new())]
+        },
+        None => {
+            let mut built = BTreeMap::new();
+            graph.roots().iter().map(|root| graph.dependencies(root, "", &mut vec![], &mut cycles, &mut built)).collect()
+        },
+    };
+
+    match format {
+        TreeFormat::Json => println!("{}", serde_json::to_string_pretty(&nodes).map_err(|e| PieError::Failed(e.to_string()))?),
+        TreeFormat::Dot => print!("{}", dot(&nodes, reverse.is_some())),
+        TreeFormat::Text => {
+            for node in &nodes {
+                print_text(node, "", None);
+            }
+            for conflict in graph.conflicts() {
+                println!("{}", Color::Red.paint(format!("X |> {}", conflict)));
+            }
+            for cycle in &cycles {
+                println!("{}", Color::Yellow.paint(format!("? |> Dependency cycle: {}", cycle.join(" -> "))));
+            }
+        },
+    }
+    Ok(())
+}
+
+fn print_text(node: &Node, prefix: &str, last: Option<bool>) {
+    let (branch, indent) = match last {
+        None => ("", ""),
+        Some(true) => ("└── ", "    "),
+        Some(false) => ("├── ", "│   "),
+    };
+
+    let mut line = format!("{} {}", node.name, node.version.as_deref().unwrap_or("[not installed]"));
+    if !node.required.is_empty() {
+        line.push_str(&format!(" ({})", node.required));
+    }
+    let mut line = if node.top_level { Color::Green.bold().paint(line).to_string() } else { line };
+    if node.conflict {
+        line.push_str(&Color::Red.paint(" conflict").to_string());
+    }
+    if node.cycle {
+        line.push_str(&Color::Yellow.paint(" cycle").to_string());
+    }
+    println!("{}{}{}", prefix, branch, line);
+
+    let children = if node.required_by.is_empty() { &node.dependencies } else { &node.required_by };
+    let prefix = format!("{}{}", prefix, indent);
+    for (i, child) in children.iter().enumerate() {
+        print_text(child, &prefix, Some(i == children.len() - 1));
+    }
+}
+
+/// A Graphviz digraph with an edge from every package to each one it depends on. Top level packages
+/// are drawn bold, packages that aren't installed dashed, and requirements that aren't satisfied red.
+fn dot(nodes: &[Node], reverse: bool) -> String {
+    let mut packages = BTreeMap::new();
+    let mut edges = BTreeSet::new();
+    let mut stack = nodes.iter().collect::<Vec<&Node>>();
+
+    while let Some(node) = stack.pop() {
+        packages.insert(node.name.to_owned(), (node.version.to_owned(), node.top_level));
+        let children = if reverse { &node.required_by } else { &node.dependencies };
+        for child in children {
+            let (from, to) = if reverse { (&child.name, &node.name) } else { (&node.name, &child.name) };
+            edges.insert((from.to_owned(), to.to_owned(), child.required.to_owned(), child.conflict));
+            stack.push(child);
+        }
+    }
+
+    let mut dot = String::from("digraph dependencies {\n    node [shape=box];\n");
+    for (name, (version, top_level)) in &packages {
+        let style = match (version, top_level) {
+            (None, _) => ", style=dashed",
+            (_, true) => ", style=bold",
+            _ => "",
+        };
+        dot.push_str(&format!("    {:?} [label={:?}{}];\n", name, format!("{}\n{}", name, version.as_deref().unwrap_or("not installed")), style));
+    }
+    for (from, to, required, conflict) in &edges {
+        let color = if *conflict { ", color=red" } else { "" };
+        dot.push_str(&format!("    {:?} -> {:?} [label={:?}{}];\n", from, to, required, color));
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+/// The cycle starting and ending at `path`'s first package, rotated to start at the smallest
+/// name so the same cycle found from different packages is reported once.
+fn cycle(path: &[String]) -> Vec<String> {
+    let start = path.iter().enumerate().min_by_key(|(_, name)| *name).map_or(0, |(i, _)| i);
+    let mut cycle = path[start..].iter().chain(&path[..start]).cloned().collect::<Vec<String>>();
+    cycle.push(cycle[0].to_owned());
+    cycle
+}
+
+fn satisfies(version: &str, specifiers: &str) -> bool {
+    match (version.parse::<Version>(), specifiers.parse::<Specifiers>()) {
+        (Ok(version), Ok(specifiers)) => specifiers.matches(&version),
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::python::dists::Dependency;
+
+    fn graph(edges: &[(&str, &[&str])]) -> Graph {
+        let dists = edges.iter().map(|(name, requires)| (name.to_string(), Dist {
+            name: name.to_string(),
+            version: String::from("1.0"),
+            top_level: vec![],
+            requires: requires.iter().map(|name| Dependency { name: name.to_string(), specifiers: String::from(">=1"), conditional: false }).collect(),
+            info_dir: PathBuf::new(),
+        })).collect();
+        Graph { dists, top_level: BTreeSet::new() }
+    }
+
+    /// The tree as `name(child, ...)`.
+    fn shape(node: &Node) -> String {
+        let children = node.dependencies.iter().chain(&node.required_by).map(shape).collect::<Vec<String>>();
+        let name = if node.cycle { format!("{}!", node.name) } else { node.name.to_owned() };
+        if children.is_empty() { name } else { format!("{}({})", name, children.join(", ")) }
+    }
+
+    #[test]
+    fn reverse_tree() {
+        let graph = graph(&[("app", &["web", "cli"]), ("web", &["core"]), ("cli", &["core"]), ("core", &[]), ("other", &[])]);
+        let node = graph.dependents("core", &mut vec![], &mut BTreeSet::new(), &mut BTreeMap::new());
+        assert_eq!(shape(&node), "core(cli(app), web(app))");
+        assert_eq!(node.required_by[0].required, ">=1");
+    }
+
+    #[test]
+    fn reverse_tree_reuses_shared_dependents() {
+        let graph = graph(&[("top", &["a", "b"]), ("a", &["mid"]), ("b", &["mid"]), ("mid", &["x", "y"]), ("x", &["base"]), ("y", &["base"]), ("base", &[])]);
+        let mut built = BTreeMap::new();
+        let node = graph.dependents("base", &mut vec![], &mut BTreeSet::new(), &mut built);
+        assert_eq!(shape(&node), "base(x(mid(a(top), b(top))), y(mid(a(top), b(top))))");
+        assert!(built.contains_key("mid") && built.contains_key("top"));
+    }
+
+    #[test]
+    fn cycles_are_cut_and_reported() {
+        let graph = graph(&[("a", &["b"]), ("b", &["c"]), ("c", &["a"])]);
+
+        let mut cycles = BTreeSet::new();
+        assert_eq!(shape(&graph.dependencies("b", "", &mut vec![], &mut cycles, &mut BTreeMap::new())), "b(c(a(b!)))");
+        assert_eq!(cycles.into_iter().collect::<Vec<_>>(), [["a", "b", "c", "a"]]);
+
+        let mut cycles = BTreeSet::new();
+        let mut built = BTreeMap::new();
+        assert_eq!(shape(&graph.dependents("b", &mut vec![], &mut cycles, &mut built)), "b(a(c(b!)))");
+        assert_eq!(cycles.into_iter().collect::<Vec<_>>(), [["a", "b", "c", "a"]]);
+        assert!(built.is_empty());
+    }
+
+    #[test]
+    fn forward_tree_reuses_shared_dependencies() {
+        let mut graph = graph(&[("app", &["web", "cli"]), ("web", &["core"]), ("cli", &["core"]), ("core", &["base"]), ("base", &[])]);
+        graph.dists.get_mut("cli").unwrap().requires[0].specifiers = String::from(">=2");
+
+        let mut built = BTreeMap::new();
+        let node = graph.dependencies("app", "", &mut vec![], &mut BTreeSet::new(), &mut built);
+        assert_eq!(shape(&node), "app(web(core(base)), cli(core(base)))");
+        assert!(built.contains_key("core") && built.contains_key("app"));
+
+        // The reused tree takes the requirement of the edge it is reached through.
+        let (web_core, cli_core) = (&node.dependencies[0].dependencies[0], &node.dependencies[1].dependencies[0]);
+        assert_eq!((web_core.required.as_str(), web_core.conflict), (">=1", false));
+        assert_eq!((cli_core.required.as_str(), cli_core.conflict), (">=2", true));
+    }
+
+    #[test]
+    fn roots_include_a_member_of_each_unreached_cycle() {
+        let cycles_only = graph(&[("a", &["b"]), ("b", &["a", "c"]), ("c", &[]), ("x", &["y"]), ("y", &["z"]), ("z", &["x"])]);
+        assert_eq!(cycles_only.roots(), ["a", "x"]);
+
+        let reached = graph(&[("app", &["x"]), ("x", &["y"]), ("y", &["x"])]);
+        assert_eq!(reached.roots(), ["app"]);
+    }
+}
@@ -0,0 +1,150 @@
+use std::fs;
+
+use ansi_term::Color;
+use clap::Subcommand;
+use pie::{PieError, Result};
+use walkdir::WalkDir;
+
+use crate::{project::Project, python::{dists, interpreters, lock::is_locked, venv}};
+
+use super::in_commands::{create_venv, remove_venv, warn_python_mismatch};
+
+#[derive(Debug, Subcommand)]
+pub enum VenvSubCommands {
+    /// Shows where the venv is, what made it and what is in it.
+    #[clap(long_about("Shows the venv's path, the backend that made it, its python's version and interpreter, the number of packages installed in it and its size on disk. Points out a venv that was moved, or whose base python is gone."))]
+    Info,
+
+    /// Creates the venv, if there isn't one.
+    #[clap(long_about("Creates the venv with the project's backend and python, and installs pie.lock into it if the project has one, or else requirements.txt."))]
+    Create,
+
+    /// Deletes the venv and builds it again from pie.lock or requirements.txt.
+    #[clap(long_about("Deletes the venv, and everything installed in it, then creates it again with the project's backend and python and installs pie.lock into it if the project has one, or else requirements.txt."))]
+    Recreate,
+
+    /// Deletes the venv.
+    Remove,
+
+    /// Fixes a venv left broken by moving or renaming the project.
+    #[clap(long_about("Fixes a venv left broken by moving or renaming the project: rewrites the old location in the shebangs of its scripts, its activate scripts and its pyvenv.cfg. With central_venvs, the venv the project had in the store under its old location is moved to the new one first. A venv whose base python is gone can't be repaired, recreate it instead."))]
+    Repair,
+}
+
+pub fn info(project: &Project) -> Result<()> {
+    let venv_path = project.venv();
+    if let Some(old) = project.abandoned_venvs()?.first().filter(|_| !venv_path.exists()) {
+        return Err(PieError::Venv(format!("The project was moved or renamed, and its venv is still at '{}'. Run 'pie venv repair' to move it.", old.display())));
+    }
+    if !venv_path.exists() {
+        return Err(PieError::Venv(format!("No venv at '{}'. Run 'pie venv create' to create it.", venv_path.display())));
+    }
+
+    let installed = dists::installed(&venv_path);
+    let packages = installed.iter().filter(|dist| is_locked(&dist.name)).count();
+    let size = WalkDir::new(&venv_path).into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.metadata().ok())
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+        .sum::<u64>();
+    let interpreter = interpreters::probe(&project.python());
+
+    let row = |key: &str, value: String| println!("{}{}", Color::Green.bold().paint(format!("{:<12}", key)), value);
+    row("Path", venv_path.display().to_string());
+    row("Backend", project.env.name().to_string());
+    row("Python", project.python_version().unwrap_or_else(|| String::from("unknown")));
+    row("Interpreter", match &interpreter {
+        Some(interpreter) => interpreter.path.display().to_string(),
+        None => format!("{} (doesn't run)", project.python().display()),
+    });
+    if let Some(pin) = project.python_pin() {
+        row("Pinned", pin.to_string());
+    }
+    row("Packages", format!("{} (and {} that come with every venv)", packages, installed.len() - packages));
+    row("Size", human_size(size));
+
+    if let Some(old) = venv::original_location(&bin_dir(project)).filter(|old| *old != venv_path) {
+        println!("{}", Color::Yellow.paint(format!("? |> The venv was made at '{}' and has been moved since. Run 'pie venv repair' to fix it.", old.display())));
+    }
+    if venv::base_home(&venv_path).is_some_and(|home| !home.is_dir()) {
+        println!("{}", Color::Yellow.paint("? |> The python the venv was made from is gone. Run 'pie venv recreate' to rebuild it."));
+    }
+    warn_python_mismatch(project);
+    Ok(())
+}
+
+pub fn create(project: &Project) -> Result<()> {
+    if project.venv().exists() {
+        return Err(PieError::Failed(format!("There already is a venv at '{}'. Use 'pie venv recreate' to rebuild it.", project.venv().display())));
+    }
+    create_venv(project)
+}
+
+pub fn recreate(project: &Project) -> Result<()> {
+    remove_venv(&project.venv())?;
+    create_venv(project)
+}
+
+pub fn remove(project: &Project) -> Result<()> {
+    let venv_path = project.venv();
+    if !venv_path.exists() {
+        return Err(PieError::Failed(format!("There is no venv at '{}'.", venv_path.display())));
+    }
+    remove_venv(&venv_path)?;
+    println!("{}", Color::Green.paint(format!("√ |> Removed the venv at '{}'.", venv_path.display())));
+    Ok(())
+}
+
+/// Points the venv's scripts and pyvenv.cfg at where the venv is now. A venv in the central store
+/// stays where it is when the project moves, but under the store name of its old location, so it
+/// is first moved to the new one.
+pub fn repair(project: &Project) -> Result<()> {
+    let venv_path = project.venv();
+    if project.env.name() == "conda" {
+        return Err(PieError::Venv(String::from("Conda environments can't be moved. Run 'pie venv recreate' to rebuild it.")));
+    }
+    if !venv_path.exists() {
+        let abandoned = project.abandoned_venvs()?;
+        match abandoned.as_slice() {
+            [] => return Err(PieError::Venv(format!("No venv at '{}'. Run 'pie venv create' to create it.", venv_path.display()))),
+            [old] => {
+                fs::rename(old, &venv_path)?;
+                project.claim_venv()?;
+                println!("{}", Color::Green.paint(format!("√ |> Moved the project's venv in the store from '{}'.", old.display())));
+            },
+            _ => return Err(PieError::Venv(format!(
+                "No venv at '{}', and several venvs in the store could have been this project's: {}. Run 'pie venv create' to create a new one.",
+                venv_path.display(), abandoned.iter().map(|old| format!("'{}'", old.display())).collect::<Vec<String>>().join(", ")))),
+        }
+    }
+    if let Some(home) = venv::base_home(&venv_path).filter(|home| !home.is_dir()) {
+        return Err(PieError::Venv(format!("The python the venv was made from, in '{}', is gone. Run 'pie venv recreate' to rebuild it.", home.display())));
+    }
+
+    let bin = bin_dir(project);
+    match venv::original_location(&bin) {
+        Some(old) if old != venv_path => {
+            let fixed = venv::relocate(&venv_path, &bin, &old)?;
+            println!("{}", Color::Green.paint(format!("√ |> Repaired the venv moved from '{}': {} file(s) rewritten.", old.display(), fixed)));
+        },
+        _ => println!("{}", Color::Green.paint("√ |> The venv is where it was made, nothing to repair.")),
+    }
+    Ok(())
+}
+
+/// The folder holding the venv's scripts, the first one the backend puts on PATH.
+fn bin_dir(project: &Project) -> std::path::PathBuf {
+    project.env.bin_dirs(&project.venv()).remove(0)
+}
+
+fn human_size(bytes: u64) -> String {
+    let mut size = bytes as f64;
+    for unit in ["B", "KiB", "MiB", "GiB"] {
+        if size < 1024.0 || unit == "GiB" {
+            return if unit == "B" { format!("{} B", bytes) } else { format!("{:.1} {}", size, unit) };
+        }
+        size /= 1024.0;
+    }
+    unreachable!()
+}
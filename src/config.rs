@@ -1,11 +1,14 @@
+use std::collections::BTreeMap;
 use std::fs;
-use std::process;
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, ser::PrettyFormatter, Serializer};
 use directories::ProjectDirs;
-use std::io::{self, ErrorKind};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::ops::Range;
 
-use pie::{input, Result};
+use pie::{input, PieError, Result};
+
+use crate::python::{env::EnvKind, interpreters::PythonPin};
 
 use ansi_term::Color;
 
@@ -15,10 +18,33 @@ pub struct MainConfig {
     pub dev: String,
     pub email: String,
     pub github: String,
+
+    /// Seconds a python process gets to exit after pie forwards it a signal, before it is killed.
+    #[serde(default = "default_kill_grace_period")]
+    pub kill_grace_period: u64,
+
+    /// The simple index `pie outdated` looks up versions in: a URL, or a local folder.
+    #[serde(default = "default_index_url")]
+    pub index_url: String,
+
+    /// A folder or zip of OSV advisories that `pie audit` checks packages against.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub advisory_db: Option<PathBuf>,
+
+    /// What makes project environments, unless project.json says otherwise: venv, virtualenv, uv or conda.
+    #[serde(default)]
+    pub env_backend: EnvKind,
+
+    /// Keeps the environments of projects without a `venv_dir` in pie's cache folder, out of the project.
+    #[serde(default)]
+    pub central_venvs: bool,
 }
+pub fn default_kill_grace_period() -> u64 { 10 }
+pub fn default_index_url() -> String { String::from("https://pypi.org/simple") }
+
     impl MainConfig {
         pub fn new(dev: &str, email: &str, github: &str) -> MainConfig {
-            MainConfig { dev: dev.to_string(), email: email.to_string(), github: github.to_string() }
+            MainConfig { dev: dev.to_string(), email: email.to_string(), github: github.to_string(), kill_grace_period: default_kill_grace_period(), index_url: default_index_url(), advisory_db: None, env_backend: EnvKind::default(), central_venvs: false }
         }
         pub fn from_file() -> Result<MainConfig> {
             let path = MainConfig::get_file_loc()?;
@@ -37,15 +63,22 @@ pub struct MainConfig {
                 return Ok(conf);
             }
 
-            let deserialized = fs::read_to_string(MainConfig::get_file_loc()?)?;
-            Ok(serde_json::from_str(&deserialized)?)
+            let deserialized = fs::read_to_string(&path)?;
+            serde_json::from_str(&deserialized).map_err(|e| PieError::Config(format!("'{}': {}. Run 'pie cfg' to rewrite it.", path.display(), e)))
         }
 
         fn get_path() -> Result<PathBuf> {
             if let Some(project_dir) = ProjectDirs::from("com", "terroid", "pie"){
                 return Ok(project_dir.config_dir().to_owned());
             }
-            Err(Box::new(io::Error::new(ErrorKind::InvalidData, "No path found")))
+            Err(PieError::Config(String::from("Could not find a config directory for pie.")))
+        }
+        /// The folder `central_venvs` keeps environments in, e.g. ~/.cache/pie/venvs on Linux.
+        pub fn venv_store() -> Result<PathBuf> {
+            match ProjectDirs::from("com", "terroid", "pie") {
+                Some(project_dir) => Ok(project_dir.cache_dir().join("venvs")),
+                None => Err(PieError::Config(String::from("Could not find a cache directory for pie."))),
+            }
         }
         pub fn get_file_loc() -> Result<PathBuf> {
             Ok(MainConfig::get_path()?.join("config.json"))
@@ -57,19 +90,20 @@ pub struct MainConfig {
                 fs::create_dir_all(&path)?;
             }
 
-            let serialized = serde_json::to_string_pretty(&self)?;
+            let serialized = serde_json::to_string_pretty(&self).map_err(|e| PieError::Failed(e.to_string()))?;
             fs::write(MainConfig::get_file_loc()?, serialized)?;
             Ok(())
         }
     }
 /////////////////////////////////////////////////////
 
-pub fn start_config_if_not(config_loc: &PathBuf) -> Result<()>{
+/// Runs the first-time setup if there is no config yet. Returns whether it ran.
+pub fn start_config_if_not(config_loc: &Path) -> Result<bool>{
     if !config_loc.exists() {
         MainConfig::from_file()?;
-        process::exit(0);
+        return Ok(true);
     }
-    Ok(())
+    Ok(false)
 }
 
 /////////////////////////////////////////////////////
@@ -84,7 +118,76 @@ pub struct ProjectConfig{
     pub entry_point: String,
     pub working_directory: String,
     github: Option<String>,
-    license: Option<String>
+    pub license: Option<String>,
+
+    /// Files that `ver` keeps in sync with `version`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version_files: Option<Vec<VersionFile>>,
+
+    /// Named tasks runnable with `pie task <name>`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scripts: Option<BTreeMap<String, Script>>,
+
+    /// Named groups of requirements that aren't needed at runtime, e.g. `"dev": ["pytest>=7", "black"]`.
+    /// They are kept out of requirements.txt.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub groups: Option<BTreeMap<String, Vec<String>>>,
+
+    /// The licences dependencies may or may not have, checked by `pie licenses`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub licenses: Option<LicensePolicy>,
+
+    /// What makes this project's environment, overriding `env_backend` in pie's config.json.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub env_backend: Option<EnvKind>,
+
+    /// The python the environment is made with: a version like `3.11` or `>=3.10,<3.13`, or an interpreter's path.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub python: Option<PythonPin>,
+
+    /// Where the environment lives, relative to the project root. Defaults to `venv`, or a
+    /// folder in pie's cache with `central_venvs` set in pie's config.json.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub venv_dir: Option<PathBuf>
+}
+
+/// Entries are SPDX ids (`MIT`, `GPL-3.0-or-later`), licence families (`GPL` for every version
+/// of the GPL, but not the LGPL), or a licence text as `pie licenses` shows it, like `BSD License`.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct LicensePolicy {
+    /// If not empty, every dependency must be usable under these licences alone.
+    #[serde(default)]
+    pub allow: Vec<String>,
+
+    /// No dependency may require one of these licences.
+    #[serde(default)]
+    pub deny: Vec<String>,
+}
+
+/// A file containing the project's version, relative to the project root.
+/// A bare path rewrites `__version__ = "..."` in `.py` files and the whole
+/// content of any other file. The object form rewrites whatever sits where
+/// `{version}` is in `pattern`, e.g. `"badge/version-{version}-blue"`.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(untagged, expecting = "expected a path or {\"path\": ..., \"pattern\": ...}")]
+pub enum VersionFile {
+    Path(String),
+    Pattern { path: String, pattern: String }
+}
+
+/// A task from the `scripts` section. Either a shell command (`"pytest -q"`),
+/// a list of other tasks to run in order (`["lint", "test"]`), or both:
+/// `{"deps": ["lint"], "cmd": "pytest -q"}`.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(untagged, expecting = "expected a command, a list of tasks or {\"deps\": [...], \"cmd\": ...}")]
+pub enum Script {
+    Cmd(String),
+    Deps(Vec<String>),
+    Full {
+        #[serde(default)]
+        deps: Vec<String>,
+        cmd: Option<String>
+    }
 }
     impl ProjectConfig {
         pub fn new(name: &str, description: &str, config: &MainConfig) -> ProjectConfig{
@@ -97,12 +200,279 @@ pub struct ProjectConfig{
                 entry_point: name.to_string() + ".py",
                 working_directory: String::from("src"),
                 github: Some(String::from("")),
-                license: Some(String::from("MIT"))
+                license: Some(String::from("MIT")),
+                version_files: None,
+                scripts: None,
+                groups: None,
+                licenses: None,
+                env_backend: None,
+                python: None,
+                venv_dir: None
             }
         }
+
+        pub fn name(&self) -> Option<&str> {
+            self.name.as_deref()
+        }
     }
 ////////////////////////////////////////////////////
 
+/// project.json as it sits on disk. Commands that change the project config edit
+/// it through here instead of re-serializing `ProjectConfig`. Only the values that
+/// are set get rewritten, so keys pie doesn't know about, key order and the
+/// formatting of everything else survive untouched.
+pub struct ProjectFile {
+    path: PathBuf,
+    raw: String,
+    doc: Map<String, Value>,
+}
+    impl ProjectFile {
+        pub fn open(path: &Path) -> Result<ProjectFile> {
+            let raw = fs::read_to_string(path)?;
+            let invalid = |reason: String| PieError::InvalidProject { path: path.to_owned(), reason };
+            let doc = match serde_json::from_str(&raw).map_err(|e| invalid(describe_json_error(&e, None)))? {
+                Value::Object(doc) => doc,
+                _ => return Err(invalid(String::from("expected a JSON object"))),
+            };
+
+            Ok(ProjectFile { path: path.to_owned(), raw, doc })
+        }
+
+        /// Deserializes the file, reporting the line, column and field of the first problem.
+        pub fn config(&self) -> Result<ProjectConfig> {
+            let mut de = serde_json::Deserializer::from_str(&self.raw);
+            serde_path_to_error::deserialize(&mut de).map_err(|e| {
+                let field = e.path().to_string();
+                let field = (field != ".").then_some(field);
+                PieError::InvalidProject { path: self.path.to_owned(), reason: describe_json_error(e.inner(), field.as_deref()) }
+            })
+        }
+
+        pub fn get(&self, key: &str) -> Option<&Value> {
+            self.doc.get(key)
+        }
+
+        /// Line and column (both 1 based) of a top level key's value.
+        pub fn position(&self, key: &str) -> Option<(usize, usize)> {
+            let (_, _, span) = top_level_entries(&self.raw).into_iter().find(|(name, _, _)| name == key)?;
+            let before = &self.raw[..span.start];
+            let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+            Some((before.matches('\n').count() + 1, span.start - line_start + 1))
+        }
+
+        /// Sets a top level key. Existing keys are rewritten in place, new ones are appended.
+        pub fn set<T: Serialize>(&mut self, key: &str, value: T) -> Result<()> {
+            let value = serde_json::to_value(value).map_err(|e| PieError::Failed(e.to_string()))?;
+            let newline = if self.raw.contains("\r\n") { "\r\n" } else { "\n" };
+            let multiline = self.raw.trim().contains('\n');
+            let unit = self.indent_unit();
+            let entries = top_level_entries(&self.raw);
+
+            if let Some((_, key_start, span)) = entries.iter().find(|(name, _, _)| name == key) {
+                let line_indent = indent_of_line(&self.raw, *key_start);
+                let rendered = render(&value, multiline.then_some(unit.as_str()), line_indent, newline)?;
+                self.raw.replace_range(span.clone(), &rendered);
+            } else {
+                let (entry_indent, sep, colon) = if multiline { (unit.clone(), newline, ": ") } else { (String::new(), "", ":") };
+                let rendered = render(&value, multiline.then_some(unit.as_str()), &entry_indent, newline)?;
+                let entry = format!("{}{}{}{}", entry_indent, serde_json::to_string(key).map_err(|e| PieError::Failed(e.to_string()))?, colon, rendered);
+
+                match entries.last() {
+                    Some((_, _, span)) => self.raw.insert_str(span.end, &format!(",{}{}", sep, entry)),
+                    None => {
+                        let open = self.raw.find('{').unwrap_or(0) + 1;
+                        let close = self.raw.rfind('}').unwrap_or(self.raw.len());
+                        self.raw.replace_range(open..close, &format!("{}{}{}", sep, entry, sep));
+                    }
+                }
+            }
+
+            self.doc.insert(key.to_string(), value);
+            Ok(())
+        }
+
+        pub fn save(&self) -> Result<()> {
+            fs::write(&self.path, &self.raw)?;
+            Ok(())
+        }
+
+        /// The indentation step used by the file, falling back to the two spaces pie writes.
+        fn indent_unit(&self) -> String {
+            self.raw.lines()
+                .skip(1)
+                .map(|line| &line[..line.len() - line.trim_start().len()])
+                .find(|indent| !indent.is_empty())
+                .unwrap_or("  ")
+                .to_string()
+        }
+    }
+
+/// Formats a problem in project.json as "line L, column C, field 'F': message".
+pub fn describe_problem(position: Option<(usize, usize)>, field: Option<&str>, msg: &str) -> String {
+    let location = match (position, field) {
+        (Some((line, column)), Some(field)) => format!("line {}, column {}, field '{}'", line, column, field),
+        (Some((line, column)), None) => format!("line {}, column {}", line, column),
+        (None, Some(field)) => format!("field '{}'", field),
+        (None, None) => return msg.to_string(),
+    };
+    format!("{}: {}", location, msg)
+}
+
+fn describe_json_error(e: &serde_json::Error, field: Option<&str>) -> String {
+    // serde_json appends the position to its messages, it is reported separately here.
+    let msg = e.to_string();
+    let msg = msg.strip_suffix(&format!(" at line {} column {}", e.line(), e.column())).unwrap_or(&msg);
+    let position = (e.line() != 0).then_some((e.line(), e.column()));
+    describe_problem(position, field, msg)
+}
+
+fn render(value: &Value, unit: Option<&str>, line_indent: &str, newline: &str) -> Result<String> {
+    let unit = match unit {
+        Some(unit) => unit,
+        None => return serde_json::to_string(value).map_err(|e| PieError::Failed(e.to_string())),
+    };
+
+    let mut buf = Vec::new();
+    let mut ser = Serializer::with_formatter(&mut buf, PrettyFormatter::with_indent(unit.as_bytes()));
+    value.serialize(&mut ser).map_err(|e| PieError::Failed(e.to_string()))?;
+    Ok(String::from_utf8(buf)?.replace('\n', &format!("{}{}", newline, line_indent)))
+}
+
+fn indent_of_line(raw: &str, pos: usize) -> &str {
+    let line_start = raw[..pos].rfind('\n').map_or(0, |i| i + 1);
+    let line = &raw[line_start..pos];
+    &line[..line.len() - line.trim_start().len()]
+}
+
+/// Key name, key offset and value span of every member of the top level object.
+/// Only called on text serde_json already accepted, so it doesn't re-validate.
+fn top_level_entries(raw: &str) -> Vec<(String, usize, Range<usize>)> {
+    let bytes = raw.as_bytes();
+    let mut entries = vec![];
+    let mut i = skip_ws(bytes, 0) + 1;
+
+    loop {
+        i = skip_ws(bytes, i);
+        if i >= bytes.len() || bytes[i] == b'}' {
+            break;
+        }
+
+        let key_start = i;
+        i = skip_value(bytes, i);
+        let key = serde_json::from_str(&raw[key_start..i]).unwrap_or_default();
+        i = skip_ws(bytes, i) + 1;
+        i = skip_ws(bytes, i);
+
+        let value_start = i;
+        i = skip_value(bytes, i);
+        entries.push((key, key_start, value_start..i));
+
+        i = skip_ws(bytes, i);
+        if i < bytes.len() && bytes[i] == b',' {
+            i += 1;
+        }
+    }
+
+    entries
+}
+
+fn skip_ws(bytes: &[u8], mut i: usize) -> usize {
+    while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+        i += 1;
+    }
+    i
+}
+
+fn skip_value(bytes: &[u8], mut i: usize) -> usize {
+    let mut depth = 0;
+    let mut in_string = false;
+
+    while i < bytes.len() {
+        let b = bytes[i];
+        if in_string {
+            match b {
+                b'\\' => i += 1,
+                b'"' => {
+                    in_string = false;
+                    if depth == 0 {
+                        return i + 1;
+                    }
+                },
+                _ => {}
+            }
+        } else {
+            match b {
+                b'"' => in_string = true,
+                b'{' | b'[' => depth += 1,
+                b'}' | b']' if depth == 0 => return i,
+                b'}' | b']' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return i + 1;
+                    }
+                },
+                b',' if depth == 0 => return i,
+                _ if depth == 0 && b.is_ascii_whitespace() => return i,
+                _ => {}
+            }
+        }
+        i += 1;
+    }
+    i
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edited(raw: &str, key: &str, value: Value) -> String {
+        let mut file = ProjectFile { path: PathBuf::new(), raw: raw.to_string(), doc: serde_json::from_str(raw).unwrap() };
+        file.set(key, value).unwrap();
+        serde_json::from_str::<Value>(&file.raw).expect("the rewritten file is still JSON");
+        file.raw
+    }
+
+    #[test]
+    fn unknown_keys_and_order_survive() {
+        let raw = "{\n  \"x-tool\": {\"keep\": [1, 2]},\n  \"version\": \"1.0.0\",\n  \"zzz\": true\n}\n";
+        assert_eq!(edited(raw, "version", "1.1.0".into()), "{\n  \"x-tool\": {\"keep\": [1, 2]},\n  \"version\": \"1.1.0\",\n  \"zzz\": true\n}\n");
+    }
+
+    #[test]
+    fn crlf_line_endings() {
+        let raw = "{\r\n  \"version\": \"1.0.0\"\r\n}\r\n";
+        assert_eq!(edited(raw, "version", "2.0.0".into()), "{\r\n  \"version\": \"2.0.0\"\r\n}\r\n");
+        assert_eq!(edited(raw, "tags", serde_json::json!(["a"])), "{\r\n  \"version\": \"1.0.0\",\r\n  \"tags\": [\r\n    \"a\"\r\n  ]\r\n}\r\n");
+    }
+
+    #[test]
+    fn tab_and_space_indentation() {
+        assert_eq!(edited("{\n\t\"a\": 1\n}", "b", serde_json::json!({"c": 2})), "{\n\t\"a\": 1,\n\t\"b\": {\n\t\t\"c\": 2\n\t}\n}");
+        assert_eq!(edited("{\n    \"a\": 1\n}", "b", serde_json::json!({"c": 2})), "{\n    \"a\": 1,\n    \"b\": {\n        \"c\": 2\n    }\n}");
+    }
+
+    #[test]
+    fn compact_one_line() {
+        assert_eq!(edited("{\"a\":1}", "a", 2.into()), "{\"a\":2}");
+        assert_eq!(edited("{\"a\":1}", "b", serde_json::json!([1, 2])), "{\"a\":1,\"b\":[1,2]}");
+    }
+
+    #[test]
+    fn empty_object() {
+        assert_eq!(edited("{}", "a", 1.into()), "{\"a\":1}");
+        assert_eq!(edited("{\n}\n", "a", 1.into()), "{\n  \"a\": 1\n}\n");
+    }
+
+    #[test]
+    fn strings_with_json_punctuation() {
+        let raw = "{\n  \"description\": \"a {b}, c] \\\"quoted, {x}\\\"\",\n  \"version\": \"1.0.0\"\n}";
+        let out = edited(raw, "version", "1.0.1".into());
+        assert_eq!(out, raw.replace("1.0.0", "1.0.1"));
+        assert_eq!(edited(raw, "description", "new }, \"{".into()), "{\n  \"description\": \"new }, \\\"{\",\n  \"version\": \"1.0.0\"\n}");
+    }
+}
+////////////////////////////////////////////////////
+
 /*
 
 {
@@ -1,5 +1,79 @@
-use std::{io::{self, Write}, error::Error, result, process::{Command, Stdio}, ffi::OsStr};
-pub type Result<T> = result::Result<T, Box<dyn Error>>;
+use std::{io::{self, Write}, error::Error, fmt, result, path::PathBuf, process::{self, Command, ExitStatus, Stdio}, ffi::OsStr, sync::{Mutex, MutexGuard, PoisonError}, thread, time::Duration};
+pub type Result<T> = result::Result<T, PieError>;
+
+/// Everything that can make a pie command fail. Each variant exits with its own
+/// code, taken from sysexits.h so they don't collide with the usual exit codes
+/// of python scripts, which pie passes through as they are. The codes are stable.
+#[derive(Debug)]
+pub enum PieError {
+    /// A command failed for a reason the user can fix, e.g. an invalid version.
+    Failed(String),
+    NotAProject(PathBuf),
+    InvalidProject { path: PathBuf, reason: String },
+    MissingInterpreter(String),
+    Venv(String),
+    Subprocess(String),
+    Archive(String),
+    Config(String),
+    Io(io::Error),
+}
+    impl PieError {
+        pub fn exit_code(&self) -> i32 {
+            match self {
+                PieError::Failed(_) => 64,
+                PieError::InvalidProject { .. } => 65,
+                PieError::NotAProject(_) => 66,
+                PieError::MissingInterpreter(_) => 69,
+                PieError::Subprocess(_) => 70,
+                PieError::Venv(_) => 73,
+                PieError::Io(_) => 74,
+                PieError::Archive(_) => 76,
+                PieError::Config(_) => 78,
+            }
+        }
+    }
+
+    impl fmt::Display for PieError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                PieError::Failed(msg) => write!(f, "{}", msg),
+                PieError::NotAProject(path) => write!(f, "'{}' is not a pie project (no valid 'project.json' found).", path.display()),
+                PieError::InvalidProject { path, reason } => write!(f, "Invalid '{}': {}", path.display(), reason),
+                PieError::MissingInterpreter(msg) => write!(f, "Python interpreter not found: {}", msg),
+                PieError::Venv(msg) => write!(f, "Venv error: {}", msg),
+                PieError::Subprocess(msg) => write!(f, "{}", msg),
+                PieError::Archive(msg) => write!(f, "Archive error: {}", msg),
+                PieError::Config(msg) => write!(f, "Config error: {}", msg),
+                PieError::Io(e) => write!(f, "{}", e),
+            }
+        }
+    }
+
+    impl Error for PieError {}
+
+    impl From<io::Error> for PieError {
+        fn from(e: io::Error) -> PieError {
+            PieError::Io(e)
+        }
+    }
+
+    impl From<zip::result::ZipError> for PieError {
+        fn from(e: zip::result::ZipError) -> PieError {
+            PieError::Archive(e.to_string())
+        }
+    }
+
+    impl From<std::env::JoinPathsError> for PieError {
+        fn from(e: std::env::JoinPathsError) -> PieError {
+            PieError::Failed(e.to_string())
+        }
+    }
+
+    impl From<std::string::FromUtf8Error> for PieError {
+        fn from(e: std::string::FromUtf8Error) -> PieError {
+            PieError::Failed(e.to_string())
+        }
+    }
 
 pub fn input<T>(question: T) -> Result<String> 
 where
@@ -12,31 +86,161 @@ where
     Ok(guess.trim().to_string())
 }
 
-pub fn run_cmd<S, T, K>(cmd: &str, args: &Vec<S>, should_display_output: bool, err_func: T, ok_func: K)
-where
-    S: AsRef<OsStr>,
-    T: FnOnce(),
-    K: FnOnce()
+/// How a child process started by pie ended.
+#[derive(Debug)]
+pub enum CmdOutcome {
+    Exited(i32),
+    Signaled(i32),
+    SpawnFailed(io::Error),
+}
+    impl CmdOutcome {
+        pub fn success(&self) -> bool {
+            matches!(self, CmdOutcome::Exited(0))
+        }
+
+        /// Turns a child that could not be started into an error naming `cmd`.
+        pub fn spawned(self, cmd: &str) -> Result<CmdOutcome> {
+            match self {
+                CmdOutcome::SpawnFailed(e) => Err(PieError::Subprocess(format!("Could not start '{}': {}", cmd, e))),
+                outcome => Ok(outcome),
+            }
+        }
+
+        /// The code pie should exit with to mirror the child, the way shells do:
+        /// 128+n for a child killed by signal n, 127 when it could not be started.
+        pub fn exit_code(&self) -> i32 {
+            match self {
+                CmdOutcome::Exited(code) => *code,
+                CmdOutcome::Signaled(signal) => 128 + signal,
+                CmdOutcome::SpawnFailed(_) => 127,
+            }
+        }
+    }
 
+    impl From<io::Result<ExitStatus>> for CmdOutcome {
+        fn from(status: io::Result<ExitStatus>) -> CmdOutcome {
+            let status = match status {
+                Ok(status) => status,
+                Err(e) => return CmdOutcome::SpawnFailed(e),
+            };
+
+            #[cfg(unix)]
+            {
+                use std::os::unix::process::ExitStatusExt;
+                if let Some(signal) = status.signal() {
+                    return CmdOutcome::Signaled(signal);
+                }
+            }
+
+            CmdOutcome::Exited(status.code().unwrap_or(1))
+        }
+    }
+
+pub fn run_cmd<S>(cmd: &str, args: &[S], should_display_output: bool) -> CmdOutcome
+where
+    S: AsRef<OsStr>
 {
-    let out = if should_display_output {
-        Command::new(cmd)
-                            .args(args)
-                            .stdout(Stdio::inherit())
-                            .stdin(Stdio::inherit())
-                            .stderr(Stdio::inherit())
-                            .output()
-    } else {
-        Command::new(cmd)
-                            .args(args)
-                            .output()
-    };
+    let mut command = Command::new(cmd);
+    command.args(args);
+    run_cmd_with(&mut command, should_display_output)
+}
 
-    if out.is_err() || !out.unwrap().status.success(){
-        err_func();
+/// `run_cmd` for a command that is already set up, e.g. with its own environment variables.
+pub fn run_cmd_with(command: &mut Command, should_display_output: bool) -> CmdOutcome {
+    if should_display_output {
+        command.stdout(Stdio::inherit())
+                .stdin(Stdio::inherit())
+                .stderr(Stdio::inherit());
     } else {
-        ok_func();
+        command.stdout(Stdio::piped())
+                .stdin(Stdio::null())
+                .stderr(Stdio::piped());
     }
+
+    run_command(command)
+}
+
+/// Pid of the child `run_command` is waiting on, 0 when there is none. It is locked
+/// while the child is spawned, so a signal arriving then waits for its pid.
+static CHILD_PID: Mutex<u32> = Mutex::new(0);
+
+fn child_pid() -> MutexGuard<'static, u32> {
+    CHILD_PID.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Spawns `command` and waits for it. While it runs, termination signals pie
+/// receives are forwarded to it instead of killing pie (see `forward_signals`).
+pub fn run_command(command: &mut Command) -> CmdOutcome {
+    let mut pid = child_pid();
+    let child = match command.spawn() {
+        Ok(child) => child,
+        Err(e) => return CmdOutcome::SpawnFailed(e),
+    };
+    *pid = child.id();
+    drop(pid);
+
+    let status = child.wait_with_output().map(|out| out.status);
+    *child_pid() = 0;
+
+    CmdOutcome::from(status)
+}
+
+/// Installs pie's handler for SIGINT, SIGTERM and SIGHUP. With no child running,
+/// pie exits with 128+n. Otherwise the signal is passed on to the child, which gets
+/// `grace` to exit before it is sent SIGKILL. A second signal kills it right away.
+#[cfg(unix)]
+pub fn forward_signals(grace: Duration) -> Result<()> {
+    use signal_hook::{consts::{SIGHUP, SIGINT, SIGTERM}, iterator::{SignalsInfo, exfiltrator::WithOrigin}, low_level::siginfo::Cause};
+
+    let mut signals = SignalsInfo::<WithOrigin>::new([SIGINT, SIGTERM, SIGHUP])?;
+
+    thread::spawn(move || {
+        let mut escalating = 0;
+
+        for origin in signals.forever() {
+            let pid = *child_pid();
+            if pid == 0 {
+                spinach::term::show_cursor();
+                process::exit(128 + origin.signal);
+            }
+
+            if escalating == pid {
+                // SAFETY: kill(2) has no memory safety requirements.
+                unsafe { libc::kill(pid as libc::pid_t, libc::SIGKILL) };
+                continue;
+            }
+
+            // Ctrl-C in a terminal already reaches the whole foreground process group,
+            // so only a SIGINT sent to pie directly has to be passed on.
+            if origin.signal != SIGINT || matches!(origin.cause, Cause::Sent(_)) {
+                // SAFETY: kill(2) has no memory safety requirements.
+                unsafe { libc::kill(pid as libc::pid_t, origin.signal) };
+            }
+
+            escalating = pid;
+            thread::spawn(move || {
+                thread::sleep(grace);
+                if *child_pid() == pid {
+                    // SAFETY: kill(2) has no memory safety requirements.
+                    unsafe { libc::kill(pid as libc::pid_t, libc::SIGKILL) };
+                }
+            });
+        }
+    });
+
+    Ok(())
+}
+
+/// Installs pie's Ctrl-C handler. The console delivers Ctrl-C to the child as
+/// well, so while one is running pie just waits for it to exit.
+#[cfg(windows)]
+pub fn forward_signals(_grace: Duration) -> Result<()> {
+    ctrlc::set_handler(|| {
+        if *child_pid() == 0 {
+            spinach::term::show_cursor();
+            process::exit(1);
+        }
+    }).map_err(|e| PieError::Failed(e.to_string()))
 }
 
 pub fn gitignore() -> String{
@@ -413,4 +617,60 @@ $RECYCLE.BIN/
 # End of https://www.toptal.com/developers/gitignore/api/windows,linux,macos,python,pycharm,venv,visualstudiocode
 ".to_string()
 
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_codes_are_stable() {
+        let codes = [
+            (PieError::Failed(String::new()), 64),
+            (PieError::InvalidProject { path: PathBuf::new(), reason: String::new() }, 65),
+            (PieError::NotAProject(PathBuf::new()), 66),
+            (PieError::MissingInterpreter(String::new()), 69),
+            (PieError::Subprocess(String::new()), 70),
+            (PieError::Venv(String::new()), 73),
+            (PieError::Io(io::Error::other("")), 74),
+            (PieError::Archive(String::new()), 76),
+            (PieError::Config(String::new()), 78),
+        ];
+        for (error, code) in codes {
+            assert_eq!(error.exit_code(), code, "{:?}", error);
+        }
+    }
+
+    #[test]
+    fn outcomes_map_to_shell_exit_codes() {
+        assert_eq!(CmdOutcome::Exited(0).exit_code(), 0);
+        assert_eq!(CmdOutcome::Exited(3).exit_code(), 3);
+        assert_eq!(CmdOutcome::Signaled(2).exit_code(), 130);
+        assert_eq!(CmdOutcome::Signaled(15).exit_code(), 143);
+        assert_eq!(CmdOutcome::SpawnFailed(io::Error::other("")).exit_code(), 127);
+        assert!(CmdOutcome::Exited(0).success() && !CmdOutcome::Signaled(15).success());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn child_killed_by_a_signal() {
+        // Signal the child as soon as run_command has published its pid.
+        let killer = thread::spawn(|| loop {
+            let pid = *child_pid();
+            if pid != 0 {
+                // SAFETY: kill(2) has no memory safety requirements.
+                unsafe { libc::kill(pid as libc::pid_t, libc::SIGTERM) };
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        });
+
+        let outcome = run_command(Command::new("sleep").arg("30"));
+        killer.join().unwrap();
+        assert!(matches!(outcome, CmdOutcome::Signaled(15)), "{:?}", outcome);
+        assert_eq!(outcome.exit_code(), 143);
+        assert_eq!(*child_pid(), 0);
+
+        assert!(matches!(run_command(&mut Command::new("pie-no-such-program")), CmdOutcome::SpawnFailed(_)));
+        assert_eq!(*child_pid(), 0);
+    }
+}
@@ -1,80 +1,175 @@
 mod config;
+mod semver;
+mod project;
+mod cli;
 use ansi_term::Color;
 use config::{self as conf, MainConfig};
+use project::Project;
 mod commands {
     pub mod out_commands;
     pub mod in_commands;
+    pub mod tasks;
+    pub mod check;
+    pub mod lock;
+    pub mod outdated;
+    pub mod tree;
+    pub mod deps;
+    pub mod licenses;
+    pub mod audit;
+    pub mod interpreters;
+    pub mod venv;
 }
-use commands::{out_commands::{self, OutArgs}, in_commands::{self, InArgs}};
-use std::{process, path::{PathBuf, Path}, env};
-use spinach::term;
+mod python {
+    pub mod imports;
+    pub mod stdlib;
+    pub mod dists;
+    pub mod venv;
+    pub mod env;
+    pub mod interpreters;
+    pub mod requirements;
+    pub mod lock;
+    pub mod pep440;
+    pub mod index;
+    pub mod license;
+    pub mod osv;
+}
+use commands::{out_commands::{self, OutSubCommands}, in_commands::{self, InSubCommands}, tasks, check, lock, outdated, tree, licenses, audit, deps::{self, DepsSubCommands}, interpreters::{self, PythonSubCommands}, venv::{self, VenvSubCommands}};
+use cli::{Cli, Command};
+use std::{process, path::{Path, PathBuf}, env, time::Duration};
+use pie::{PieError, Result};
 use clap::Parser;
 
 ////////////////////////////////////////////////////////////////////////////////////////////////
 
 fn main() {
-    
-    let config_loc = setup();
-
-    let args = env::args().collect::<Vec<String>>();
-    if args.len() >= 2 {
-        // <pie.exe> folder <args1> <args2>
-        // <pie.exe> <abc.py> <args1> <args2>
-        let path = PathBuf::from(&args[1]);
-        if path.is_dir() {
-            out_commands::run_folder(&args[1..]).unwrap();
-            process::exit(0);
-        } else if path.is_file() {
-            out_commands::run_file(&args[1..]).unwrap();
-            process::exit(0);
-        } else if (&args[1]).ends_with(".py") {
-            println!("{}", format!("{}{}{}", Color::Red.paint("X |> '"), Color::Red.bold().paint(&args[1]), Color::Red.paint("' Python file does not exist.")));
-            process::exit(1);
+    match run() {
+        Ok(code) => process::exit(code),
+        Err(e) => {
+            eprintln!("{}", Color::Red.paint(format!("X |> {}", e)));
+            process::exit(e.exit_code());
         }
     }
+}
 
-    // IF PROJECT CONFIG (w/ VALIDATION) EXISTS
-    if let Some(mut project_conf) = out_commands::is_in_proj(&Path::new(".").to_owned()) {
-        let args = InArgs::parse();
-            match args.command {
-                in_commands::InSubCommands::Ver { ver } => { in_commands::version(ver, &mut project_conf).unwrap(); }
-                in_commands::InSubCommands::Run { args } => { in_commands::run(args, project_conf).unwrap(); }
-                in_commands::InSubCommands::Show { mut args } => { in_commands::run_pip("show", &mut args, true, Some(project_conf)).unwrap(); },
-                in_commands::InSubCommands::Pip { mut args } => { in_commands::run_pip("", &mut args, true, Some(project_conf)).unwrap(); }
-                in_commands::InSubCommands::List { mut args } => { in_commands::run_pip("list", &mut args, true, Some(project_conf)).unwrap(); }
-                in_commands::InSubCommands::Install { mut args } => { in_commands::run_pip("install", &mut args, true, Some(project_conf)).unwrap(); }
-                in_commands::InSubCommands::Uninstall { mut args } => { in_commands::run_pip("uninstall", &mut args, true, Some(project_conf)).unwrap(); }
-                in_commands::InSubCommands::Reqs { install } => { in_commands::reqs(install, true, Some(project_conf)).unwrap(); }
-                in_commands::InSubCommands::AutoInstall => {in_commands::auto_install(Some(project_conf)).unwrap();}
-                in_commands::InSubCommands::Push { commit_msg, remote, branch } => { in_commands::push(commit_msg, remote, branch).unwrap(); }
-            }
-            return;
+/// Dispatches the command line. Returns the code pie should exit with, which is the
+/// child's own code for commands that run a python process on the user's behalf.
+fn run() -> Result<i32> {
+
+    let config_loc = match setup()? {
+        Some(config_loc) => config_loc,
+        None => return Ok(0),
+    };
+
+    // <pie.exe> folder <args1> <args2>
+    // <pie.exe> <abc.py> <args1> <args2>
+    // An environment folder, like the project's own `venv`, is never something to run.
+    let is_env = |path: &Path| path.join("pyvenv.cfg").is_file() || path.join("conda-meta").is_dir();
+    let (args, warning) = cli::expand_shorthand(env::args().collect(), |path| path.exists() && !is_env(path));
+    if let Some(warning) = warning {
+        eprintln!("{}", Color::Yellow.paint(format!("|> {}", warning)));
+    }
+
+    let cli = Cli::parse_from(args);
+    if let Some(dir) = &cli.dir {
+        env::set_current_dir(dir)?;
     }
 
-    let args = OutArgs::parse();
-    match args.command {
-        out_commands::OutSubCommands::List => { out_commands::list().unwrap() }
-        out_commands::OutSubCommands::DeleteProject { name } => { out_commands::delete_project(&name).unwrap(); }
-        out_commands::OutSubCommands::Cfg => {out_commands::config(&config_loc, &conf::start_config_if_not).unwrap();}
-        out_commands::OutSubCommands::New { name, short_description } => {out_commands::new(&name, &short_description).unwrap();}
-        out_commands::OutSubCommands::Pkg { project, force } => { out_commands::pkg(&project, force).unwrap(); }
-        out_commands::OutSubCommands::Unpkg { project, force } => { out_commands::unpkg(&project, force).unwrap(); }
+    // The project in-project commands work on: the one given with --project, or else the
+    // one enclosing the current directory, from its root or any folder below it.
+    let root = match cli.project {
+        Some(project) => match Project::select(&project, &env::current_dir()?) {
+            Some(root) => Some(root),
+            None => return Err(PieError::NotAProject(project)),
+        },
+        None => Project::find_root(&env::current_dir()?),
+    };
+
+    let auto_venv = !cli.no_auto_venv;
+    match cli.command {
+        Command::In(command) => in_project(command, root, auto_venv),
+        Command::List { mut args } => match root {
+            Some(root) => {
+                let mut project = Project::load(&root)?;
+                project.auto_venv = auto_venv;
+                Ok(in_commands::run_pip("list", &mut args, true, &project)?.exit_code())
+            },
+            None if args.is_empty() => { out_commands::list()?; Ok(0) },
+            None => Err(PieError::NotAProject(env::current_dir()?)),
+        },
+        // `check` doesn't load the project, so it can report every problem in project.json.
+        Command::Check => match root {
+            Some(root) => { check::check(&root.join("project.json"))?; Ok(0) },
+            None => Err(PieError::NotAProject(env::current_dir()?)),
+        },
+        Command::Exec { path, args } => {
+            let mut path = vec![path];
+            path.extend(args);
+            Ok(out_commands::exec(&path, auto_venv)?.exit_code())
+        },
+        Command::Out(command) => {
+            match command {
+                OutSubCommands::DeleteProject { name } => { out_commands::delete_project(&name)?; }
+                OutSubCommands::Cfg => { out_commands::config(&config_loc, &conf::start_config_if_not)?; }
+                OutSubCommands::New { name, short_description } => { out_commands::new(&name, &short_description)?; }
+                OutSubCommands::Pkg { project, force } => { out_commands::pkg(&project, force)?; }
+                OutSubCommands::Unpkg { project, force } => { out_commands::unpkg(&project, force)?; }
+            }
+            Ok(0)
+        }
     }
 }
 
+fn in_project(command: InSubCommands, root: Option<PathBuf>, auto_venv: bool) -> Result<i32> {
+    let root = match root {
+        Some(root) => root,
+        None => return Err(PieError::NotAProject(env::current_dir()?)),
+    };
+
+    let mut project = Project::load(&root)?;
+    project.auto_venv = auto_venv;
+    let outcome = match command {
+        InSubCommands::Ver { ver, flags, bump } => { in_commands::version(ver, bump, flags, &mut project)?; return Ok(0); }
+        InSubCommands::Run { args } => { in_commands::run(args, &project)? }
+        InSubCommands::Task { name, list, args } => { tasks::task(name, list, args, &project)? }
+        InSubCommands::Show { mut args } => { in_commands::run_pip("show", &mut args, true, &project)? },
+        InSubCommands::Pip { mut args } => { in_commands::run_pip("", &mut args, true, &project)? }
+        InSubCommands::Install { group, dev, args } => { in_commands::install(if dev { Some(String::from("dev")) } else { group }, args, &project)? }
+        InSubCommands::Uninstall { mut args } => { in_commands::run_pip("uninstall", &mut args, true, &project)? }
+        InSubCommands::Reqs { install, dry_run } => { in_commands::reqs(install, dry_run, true, &project)?; return Ok(0); }
+        InSubCommands::Lock => { lock::lock(&project)?; return Ok(0); }
+        InSubCommands::Sync { groups } => { lock::sync(&project, &groups)?; return Ok(0); }
+        InSubCommands::Outdated { index, json, upgrade } => { outdated::outdated(index, json, upgrade, &project)?; return Ok(0); }
+        InSubCommands::Tree { reverse, format } => { tree::tree(reverse, format, &project)?; return Ok(0); }
+        InSubCommands::Deps { command: DepsSubCommands::Check } => { deps::check(&project)?; return Ok(0); }
+        InSubCommands::Licenses => { licenses::licenses(&project)?; return Ok(0); }
+        InSubCommands::Audit { db, lock: locked, fail_on, json } => { audit::audit(db, locked, fail_on, json, &project)?; return Ok(0); }
+        InSubCommands::Python { command: PythonSubCommands::List } => { interpreters::list(&project)?; return Ok(0); }
+        InSubCommands::Python { command: PythonSubCommands::Use { python } } => { interpreters::use_python(&python, &mut project)?; return Ok(0); }
+        InSubCommands::Venv { command: VenvSubCommands::Info } => { venv::info(&project)?; return Ok(0); }
+        InSubCommands::Venv { command: VenvSubCommands::Create } => { venv::create(&project)?; return Ok(0); }
+        InSubCommands::Venv { command: VenvSubCommands::Recreate } => { venv::recreate(&project)?; return Ok(0); }
+        InSubCommands::Venv { command: VenvSubCommands::Remove } => { venv::remove(&project)?; return Ok(0); }
+        InSubCommands::Venv { command: VenvSubCommands::Repair } => { venv::repair(&project)?; return Ok(0); }
+        InSubCommands::AutoInstall => { in_commands::auto_install(&project)?; return Ok(0); }
+        InSubCommands::Push { commit_msg, remote, branch } => { in_commands::push(commit_msg, remote, branch, &project)?; return Ok(0); }
+    };
+    Ok(outcome.exit_code())
+}
+
 ////////////////////////////////////////////////////////////////////////////////////////////////
 
-fn setup() -> PathBuf{
+/// Returns the config location, or None if this run was the first-time setup.
+fn setup() -> Result<Option<PathBuf>> {
     #[cfg(windows)]
     let _enabled = ansi_term::enable_ansi_support();
 
-    ctrlc::set_handler(|| {
-        term::show_cursor();
-        process::exit(1);
-    }).expect("Error setting Ctrl-C Handler");
+    let config_loc = MainConfig::get_file_loc()?;
+    if conf::start_config_if_not(&config_loc)? {
+        return Ok(None);
+    }
 
-    let config_loc = MainConfig::get_file_loc().unwrap();
-    conf::start_config_if_not(&config_loc).unwrap();
+    let grace_period = MainConfig::from_file().map_or(conf::default_kill_grace_period(), |config| config.kill_grace_period);
+    pie::forward_signals(Duration::from_secs(grace_period))?;
 
-    config_loc
-}
\ No newline at end of file
+    Ok(Some(config_loc))
+}
@@ -0,0 +1,305 @@
+use std::{env, fs, path::{Path, PathBuf}};
+
+use directories::BaseDirs;
+use pie::Result;
+use sha2::{Digest, Sha256};
+
+use crate::{config::{MainConfig, ProjectConfig, ProjectFile}, python::{env::EnvBackend, interpreters::PythonPin}};
+
+/// A pie project: the folder containing project.json, the config read from it, and the backend
+/// its environment is made with. In-project commands resolve every path against `root`, never against the process cwd.
+pub struct Project {
+    pub root: PathBuf,
+    pub config: ProjectConfig,
+    pub env: Box<dyn EnvBackend>,
+    /// Whether commands that need the environment create it when it is missing.
+    pub auto_venv: bool,
+    venv: PathBuf,
+    /// Whether `venv` is in the central store, see `MainConfig::venv_store`.
+    central: bool,
+}
+
+    impl Project {
+        /// Looks for project.json in `start` and then its parents. The search stops at the
+        /// enclosing git root or the home directory, so a stray project.json higher up is never picked.
+        pub fn find_root(start: &Path) -> Option<PathBuf> {
+            let home = BaseDirs::new().map(|dirs| dirs.home_dir().to_owned());
+
+            for dir in start.ancestors() {
+                if dir.join("project.json").is_file() {
+                    return Some(dir.to_owned());
+                }
+                if dir.join(".git").exists() || Some(dir) == home.as_deref() {
+                    break;
+                }
+            }
+            None
+        }
+
+        /// The root of the project `--project` selects: a folder holding a project.json, or else the
+        /// project of that name, by folder or by `name` in its project.json, among the projects in
+        /// `cwd`, which `pie list` shows, and those next to the project enclosing `cwd`.
+        pub fn select(selector: &Path, cwd: &Path) -> Option<PathBuf> {
+            let path = cwd.join(selector);
+            if path.join("project.json").is_file() {
+                return Some(path);
+            }
+            let name = selector.to_str().filter(|_| selector.components().count() == 1)?;
+
+            let mut projects = vec![];
+            let parent = Project::find_root(cwd).and_then(|root| root.parent().map(Path::to_owned));
+            for dir in [Some(cwd.to_owned()), parent].into_iter().flatten() {
+                let mut found = fs::read_dir(dir).into_iter().flatten()
+                    .filter_map(|entry| entry.ok())
+                    .map(|entry| entry.path())
+                    .filter(|path| path.join("project.json").is_file())
+                    .collect::<Vec<PathBuf>>();
+                found.sort();
+                projects.extend(found);
+            }
+
+            let named = |path: &&PathBuf| ProjectFile::open(&path.join("project.json")).and_then(|file| file.config()).is_ok_and(|config| config.name() == Some(name));
+            projects.iter().find(|path| path.file_name().is_some_and(|folder| folder == name))
+                .or_else(|| projects.iter().find(named))
+                .cloned()
+        }
+
+        /// Loads the project rooted at `root`, which must contain a project.json.
+        pub fn load(root: &Path) -> Result<Project> {
+            Project::load_with(root, &MainConfig::from_file()?)
+        }
+
+        /// `load` with pie's config given instead of read from its file.
+        fn load_with(root: &Path, main_config: &MainConfig) -> Result<Project> {
+            let root = env::current_dir()?.join(root);
+            let config = ProjectFile::open(&root.join("project.json"))?.config()?;
+            let env = config.env_backend.unwrap_or(main_config.env_backend).backend();
+            let central = config.venv_dir.is_none() && main_config.central_venvs;
+            let venv = match &config.venv_dir {
+                Some(venv_dir) => root.join(venv_dir),
+                None if central => MainConfig::venv_store()?.join(store_name(&root)),
+                None => root.join("venv"),
+            };
+            Ok(Project { root, config, env, auto_venv: true, venv, central })
+        }
+
+        /// Loads the project in `dir`. A folder without a project.json isn't a project, but one
+        /// with a broken project.json is an error, so the user gets told what is wrong with it.
+        pub fn open(dir: &Path) -> Result<Option<Project>> {
+            if !dir.join("project.json").exists() {
+                return Ok(None);
+            }
+            Project::load(dir).map(Some)
+        }
+
+        pub fn file(&self) -> PathBuf {
+            self.root.join("project.json")
+        }
+
+        pub fn working_dir(&self) -> PathBuf {
+            self.root.join(&self.config.working_directory)
+        }
+
+        pub fn requirements(&self) -> PathBuf {
+            self.root.join("requirements.txt")
+        }
+
+        pub fn lock_file(&self) -> PathBuf {
+            self.root.join("pie.lock")
+        }
+
+        /// Where the project's environment is, always an absolute path.
+        pub fn venv(&self) -> PathBuf {
+            self.venv.to_owned()
+        }
+
+        /// The python of the project's environment.
+        pub fn python(&self) -> PathBuf {
+            self.env.python(&self.venv())
+        }
+
+        /// The python the project asks for: `python` in project.json, or else pyenv's `.python-version`.
+        /// A relative interpreter path is relative to the project root.
+        pub fn python_pin(&self) -> Option<PythonPin> {
+            match self.config.python.clone().or_else(|| PythonPin::from_version_file(&self.root))? {
+                PythonPin::Path(path) if path.is_relative() => Some(PythonPin::Path(self.root.join(path))),
+                pin => Some(pin),
+            }
+        }
+
+        /// Records in an environment in the central store which project it belongs to, so it can
+        /// be found again after the project is moved or renamed (see `abandoned_venvs`).
+        pub fn claim_venv(&self) -> Result<()> {
+            if self.central {
+                let root = fs::canonicalize(&self.root).unwrap_or_else(|_| self.root.to_owned());
+                fs::write(self.venv.join(STORE_OWNER), format!("{}\n{}\n", root.display(), self.config.name().unwrap_or_default()))?;
+            }
+            Ok(())
+        }
+
+        /// The environments in the central store that could be the one this project had before it
+        /// was moved or renamed, which changed its store name. Empty if it doesn't use the store.
+        pub fn abandoned_venvs(&self) -> Result<Vec<PathBuf>> {
+            if !self.central {
+                return Ok(vec![]);
+            }
+            Ok(abandoned_entries(&MainConfig::venv_store()?, self.config.name().unwrap_or_default()))
+        }
+
+        /// The full version of the environment's python, if the environment exists.
+        pub fn python_version(&self) -> Option<String> {
+            self.env.python_version(&self.venv())
+        }
+    }
+
+/// The file naming the project root, and project name, an environment in the central store belongs to.
+const STORE_OWNER: &str = "pie-project";
+
+/// The entries of the central store `store` whose project, named `name`, is no longer where it was.
+fn abandoned_entries(store: &Path, name: &str) -> Vec<PathBuf> {
+    let mut entries = fs::read_dir(store).into_iter().flatten()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|entry| {
+            let owner = fs::read_to_string(entry.join(STORE_OWNER)).unwrap_or_default();
+            let mut lines = owner.lines();
+            match (lines.next(), lines.next().unwrap_or_default()) {
+                (Some(root), owner_name) => owner_name == name && !Path::new(root).join("project.json").is_file(),
+                (None, _) => false,
+            }
+        })
+        .collect::<Vec<PathBuf>>();
+    entries.sort();
+    entries
+}
+
+/// The folder name of a project's environment in the central store: the project's folder name,
+/// and a hash of its full path to tell apart projects with the same name.
+fn store_name(root: &Path) -> String {
+    let root = fs::canonicalize(root).unwrap_or_else(|_| root.to_owned());
+    let hash = format!("{:x}", Sha256::digest(root.to_string_lossy().as_bytes()));
+    let name = root.file_name().map_or_else(|| String::from("project"), |name| name.to_string_lossy().into_owned());
+    format!("{}-{}", name, &hash[..12])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::python::env::EnvKind;
+
+    fn project(root: &Path, project_json: &str, central_venvs: bool) -> Project {
+        fs::create_dir_all(root).unwrap();
+        fs::write(root.join("project.json"), project_json).unwrap();
+        let main_config = MainConfig { central_venvs, ..MainConfig::new("dev", "dev@example.com", "") };
+        Project::load_with(root, &main_config).unwrap()
+    }
+
+    #[test]
+    fn paths_resolve_against_the_root() {
+        let dir = env::temp_dir().join(format!("pie-paths-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let root = dir.join("app");
+
+        let nested = project(&root, r#"{"entry_point": "main.py", "working_directory": "src/app"}"#, false);
+        assert_eq!(nested.working_dir(), root.join("src/app"));
+        assert_eq!(nested.venv(), root.join("venv"));
+        assert_eq!((nested.requirements(), nested.lock_file()), (root.join("requirements.txt"), root.join("pie.lock")));
+
+        let venv_dir = project(&root, r#"{"entry_point": "main.py", "working_directory": "src/app", "venv_dir": "../envs/.venv"}"#, true);
+        assert_eq!(venv_dir.venv(), root.join("../envs/.venv"));
+        assert!(!venv_dir.central);
+
+        let central = project(&root, r#"{"entry_point": "main.py", "working_directory": "src/app"}"#, true);
+        assert_eq!(central.venv(), MainConfig::venv_store().unwrap().join(store_name(&root)));
+        assert!(central.central);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn backend_from_project_json_then_pies_config() {
+        let dir = env::temp_dir().join(format!("pie-backend-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let backend = |project_json: &str, main_config: EnvKind| {
+            fs::write(dir.join("project.json"), project_json).unwrap();
+            let main_config = MainConfig { env_backend: main_config, ..MainConfig::new("dev", "dev@example.com", "") };
+            Project::load_with(&dir, &main_config).unwrap().env.name()
+        };
+        let plain = r#"{"entry_point": "main.py", "working_directory": "src"}"#;
+        let conda = r#"{"entry_point": "main.py", "working_directory": "src", "env_backend": "conda"}"#;
+
+        assert_eq!(backend(plain, EnvKind::default()), "venv");
+        assert_eq!(backend(plain, EnvKind::Uv), "uv");
+        assert_eq!(backend(conda, EnvKind::Uv), "conda");
+        assert_eq!(backend(conda, EnvKind::default()), "conda");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn store_names_tell_apart_projects_of_the_same_name() {
+        let dir = env::temp_dir().join(format!("pie-store-name-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let (one, two) = (dir.join("one/app"), dir.join("two/app"));
+        fs::create_dir_all(one.join("src/app")).unwrap();
+        fs::create_dir_all(&two).unwrap();
+
+        let name = store_name(&one);
+        assert!(name.starts_with("app-") && name.len() == "app-".len() + 12, "{}", name);
+        assert_ne!(name, store_name(&two));
+        // The same folder reached through another path, like a working directory's parent, gets the same name.
+        assert_eq!(store_name(&one.join("src/app/../..")), name);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn projects_selected_by_path_or_name() {
+        let dir = env::temp_dir().join(format!("pie-select-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for (folder, name) in [("api", "api"), ("web", "frontend"), ("tools/cli", "cli")] {
+            fs::create_dir_all(dir.join(folder).join("src")).unwrap();
+            fs::write(dir.join(folder).join("project.json"), format!(r#"{{"name": "{}", "entry_point": "main.py", "working_directory": "src"}}"#, name)).unwrap();
+        }
+
+        assert_eq!(Project::select(Path::new("api"), &dir), Some(dir.join("api")));
+        assert_eq!(Project::select(Path::new("frontend"), &dir), Some(dir.join("web")));
+        assert_eq!(Project::select(Path::new("tools/cli"), &dir), Some(dir.join("tools/cli")));
+        assert_eq!(Project::select(&dir.join("web"), Path::new("/")), Some(dir.join("web")));
+        // From inside a project, its siblings are found by name too.
+        assert_eq!(Project::select(Path::new("frontend"), &dir.join("api/src")), Some(dir.join("web")));
+        assert_eq!(Project::select(Path::new("cli"), &dir), None);
+        assert_eq!(Project::select(Path::new("tools"), &dir), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn abandoned_store_entries() {
+        let dir = env::temp_dir().join(format!("pie-store-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let (store, live) = (dir.join("store"), dir.join("live"));
+        fs::create_dir_all(&live).unwrap();
+        fs::write(live.join("project.json"), "{}").unwrap();
+
+        let entry = |folder: &str, owner: Option<String>| {
+            fs::create_dir_all(store.join(folder)).unwrap();
+            if let Some(owner) = owner {
+                fs::write(store.join(folder).join(STORE_OWNER), owner).unwrap();
+            }
+        };
+        entry("live-1", Some(format!("{}\napp\n", live.display())));
+        entry("moved-2", Some(format!("{}\napp\n", dir.join("moved").display())));
+        entry("other-3", Some(format!("{}\nother\n", dir.join("gone").display())));
+        entry("unclaimed-4", None);
+
+        assert_eq!(abandoned_entries(&store, "app"), [store.join("moved-2")]);
+        assert_eq!(abandoned_entries(&store, "other"), [store.join("other-3")]);
+        assert!(abandoned_entries(&store, "new").is_empty());
+        assert!(abandoned_entries(&dir.join("no-store"), "app").is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
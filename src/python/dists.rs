@@ -0,0 +1,201 @@
+use std::{collections::BTreeSet, fs, path::{Path, PathBuf}};
+
+use super::{requirements::{requirement_name, requirement_specifiers}, venv::site_packages};
+
+/// A distribution installed in a venv, read from its .dist-info or .egg-info folder.
+#[derive(Debug)]
+pub struct Dist {
+    pub name: String,
+    pub version: String,
+    /// The top level modules it installs.
+    pub top_level: Vec<String>,
+    /// The distributions it depends on, leaving out those only needed for extras.
+    pub requires: Vec<Dependency>,
+    pub info_dir: PathBuf,
+}
+
+/// A `Requires-Dist` of a distribution.
+#[derive(Debug, Clone)]
+pub struct Dependency {
+    /// The normalized name of the distribution required.
+    pub name: String,
+    /// The version specifiers, e.g. `>=2,<3`, or an empty string.
+    pub specifiers: String,
+    /// Whether an environment marker limits it to some platforms or pythons.
+    pub conditional: bool,
+}
+
+/// Import names that are not the name of the distribution providing them. Only used for
+/// modules that aren't installed in the venv, whose own metadata is preferred.
+const KNOWN: &[(&str, &str)] = &[
+    ("Bio", "biopython"), ("Crypto", "pycryptodome"), ("Levenshtein", "python-Levenshtein"), ("MySQLdb", "mysqlclient"),
+    ("OpenGL", "PyOpenGL"), ("OpenSSL", "pyOpenSSL"), ("PIL", "Pillow"), ("Xlib", "python-xlib"),
+    ("_cffi_backend", "cffi"), ("attr", "attrs"), ("bs4", "beautifulsoup4"), ("bson", "pymongo"),
+    ("cairo", "pycairo"), ("cassandra", "cassandra-driver"), ("corsheaders", "django-cors-headers"), ("cv2", "opencv-python"),
+    ("dateutil", "python-dateutil"), ("debug_toolbar", "django-debug-toolbar"), ("discord", "discord.py"), ("dns", "dnspython"),
+    ("docx", "python-docx"), ("dotenv", "python-dotenv"), ("engineio", "python-engineio"), ("environ", "django-environ"),
+    ("fake_useragent", "fake-useragent"), ("ffmpeg", "ffmpeg-python"), ("fitz", "PyMuPDF"), ("flask_cors", "Flask-Cors"),
+    ("flask_login", "Flask-Login"), ("flask_sqlalchemy", "Flask-SQLAlchemy"), ("flask_wtf", "Flask-WTF"), ("gi", "PyGObject"),
+    ("git", "GitPython"), ("github", "PyGithub"), ("google_auth_oauthlib", "google-auth-oauthlib"), ("googleapiclient", "google-api-python-client"),
+    ("grpc", "grpcio"), ("gridfs", "pymongo"), ("igraph", "python-igraph"), ("jose", "python-jose"),
+    ("jwt", "PyJWT"), ("kafka", "kafka-python"), ("ldap", "python-ldap"), ("magic", "python-magic"),
+    ("memcache", "python-memcached"), ("mpl_toolkits", "matplotlib"), ("multipart", "python-multipart"), ("nacl", "PyNaCl"),
+    ("osgeo", "GDAL"), ("pdfminer", "pdfminer.six"), ("pkg_resources", "setuptools"), ("pptx", "python-pptx"),
+    ("pythoncom", "pywin32"), ("pywintypes", "pywin32"), ("pyximport", "Cython"), ("rest_framework", "djangorestframework"),
+    ("ruamel", "ruamel.yaml"), ("sentry_sdk", "sentry-sdk"), ("serial", "pyserial"), ("skimage", "scikit-image"),
+    ("sklearn", "scikit-learn"), ("slugify", "python-slugify"), ("snappy", "python-snappy"), ("socketio", "python-socketio"),
+    ("socks", "PySocks"), ("speech_recognition", "SpeechRecognition"), ("telegram", "python-telegram-bot"), ("usb", "pyusb"),
+    ("webdriver_manager", "webdriver-manager"), ("websocket", "websocket-client"), ("win32api", "pywin32"), ("win32com", "pywin32"),
+    ("win32con", "pywin32"), ("wx", "wxPython"), ("yaml", "PyYAML"), ("zmq", "pyzmq"),
+];
+
+/// Every distribution installed in the venv, sorted by name.
+pub fn installed(venv: &Path) -> Vec<Dist> {
+    let site_packages = match site_packages(venv) {
+        Some(site_packages) => site_packages,
+        None => return vec![],
+    };
+
+    let mut dists = fs::read_dir(site_packages).into_iter().flatten()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "dist-info" || ext == "egg-info"))
+        .filter_map(|info_dir| read_dist(&info_dir))
+        .collect::<Vec<Dist>>();
+    dists.sort_by_key(|dist| normalize(&dist.name));
+    dists
+}
+
+/// The name of the distribution that provides `module`, and its version if it is installed.
+pub fn distribution_of(module: &str, installed: &[Dist]) -> (String, Option<String>) {
+    let dist = installed.iter().find(|dist| dist.top_level.iter().any(|top_level| top_level == module))
+        .or_else(|| installed.iter().find(|dist| normalize(&dist.name) == normalize(module)));
+    if let Some(dist) = dist {
+        return (dist.name.to_owned(), Some(dist.version.to_owned()));
+    }
+
+    match KNOWN.iter().find(|(import, _)| *import == module) {
+        Some((_, name)) => (name.to_string(), None),
+        None => (module.to_string(), None),
+    }
+}
+
+/// Normalized names of `roots` and everything installed they depend on, directly or not.
+pub fn closure<I: IntoIterator<Item = String>>(roots: I, installed: &[Dist]) -> BTreeSet<String> {
+    let mut found = BTreeSet::new();
+    let mut stack = roots.into_iter().map(|root| normalize(&root)).collect::<Vec<String>>();
+
+    while let Some(name) = stack.pop() {
+        if !found.insert(name.to_owned()) {
+            continue;
+        }
+        if let Some(dist) = installed.iter().find(|dist| normalize(&dist.name) == name) {
+            stack.extend(dist.requires.iter().map(|dependency| dependency.name.to_owned()));
+        }
+    }
+    found
+}
+
+/// The values of every `key` header in a distribution's metadata, in order.
+pub fn metadata_headers(dist: &Dist, key: &str) -> Vec<String> {
+    let metadata = fs::read_to_string(dist.info_dir.join("METADATA"))
+        .or_else(|_| fs::read_to_string(dist.info_dir.join("PKG-INFO")))
+        .unwrap_or_default();
+    metadata.lines()
+        .take_while(|line| !line.is_empty())
+        .filter_map(|line| line.split_once(':').filter(|(name, _)| name.eq_ignore_ascii_case(key)))
+        .map(|(_, value)| value.trim().to_string())
+        .collect()
+}
+
+/// A distribution name normalized as in PEP 503, for comparing names.
+pub fn normalize(name: &str) -> String {
+    let mut normalized = String::with_capacity(name.len());
+    for c in name.chars() {
+        if matches!(c, '-' | '_' | '.') {
+            if !normalized.ends_with('-') {
+                normalized.push('-');
+            }
+        } else {
+            normalized.push(c.to_ascii_lowercase());
+        }
+    }
+    normalized
+}
+
+fn read_dist(info_dir: &Path) -> Option<Dist> {
+    let metadata = fs::read_to_string(info_dir.join("METADATA"))
+        .or_else(|_| fs::read_to_string(info_dir.join("PKG-INFO")))
+        .ok()?;
+    let header = |key: &str| metadata.lines()
+        .take_while(|line| !line.is_empty())
+        .find_map(|line| line.strip_prefix(key))
+        .map(|value| value.trim().to_string());
+
+    let top_level = match fs::read_to_string(info_dir.join("top_level.txt")) {
+        Ok(top_level) => top_level.lines()
+            .filter_map(|line| line.trim().split('/').next())
+            .filter(|module| !module.is_empty())
+            .map(String::from)
+            .collect(),
+        Err(_) => modules_in_record(info_dir),
+    };
+
+    Some(Dist {
+        name: header("Name:")?,
+        version: header("Version:").unwrap_or_default(),
+        top_level,
+        requires: requires(info_dir, &metadata),
+        info_dir: info_dir.to_owned(),
+    })
+}
+
+/// Newer wheels often don't ship top_level.txt, the top level modules are then taken from the installed files.
+fn modules_in_record(info_dir: &Path) -> Vec<String> {
+    let record = fs::read_to_string(info_dir.join("RECORD")).unwrap_or_default();
+    let mut modules: Vec<String> = vec![];
+
+    for line in record.lines() {
+        let path = line.split(',').next().unwrap_or_default();
+        let (first, nested) = match path.split_once('/') {
+            Some((first, _)) => (first, true),
+            None => (path, false),
+        };
+        if first.is_empty() || first == ".." || first == "__pycache__" || first.ends_with(".dist-info") || first.ends_with(".data") {
+            continue;
+        }
+
+        let module = if nested {
+            first
+        } else if let Some(module) = first.strip_suffix(".py") {
+            module
+        } else if first.ends_with(".so") || first.ends_with(".pyd") {
+            first.split('.').next().unwrap_or_default()
+        } else {
+            continue;
+        };
+        if !modules.iter().any(|known| known == module) {
+            modules.push(module.to_string());
+        }
+    }
+    modules
+}
+
+/// The Requires-Dist headers of the metadata, or for an .egg-info, the lines of requires.txt
+/// before its first `[extra]` section.
+fn requires(info_dir: &Path, metadata: &str) -> Vec<Dependency> {
+    let egg_requires = fs::read_to_string(info_dir.join("requires.txt")).unwrap_or_default();
+    let requirements = if egg_requires.is_empty() {
+        metadata.lines().take_while(|line| !line.is_empty()).filter_map(|line| line.strip_prefix("Requires-Dist:")).collect::<Vec<&str>>()
+    } else {
+        egg_requires.lines().take_while(|line| !line.starts_with('[')).collect()
+    };
+
+    requirements.into_iter()
+        .filter(|requirement| !requirement.split_once(';').is_some_and(|(_, marker)| marker.contains("extra")))
+        .filter_map(|requirement| {
+            let (name, specifiers) = requirement_specifiers(requirement).or_else(|| Some((requirement_name(requirement)?, String::new())))?;
+            Some(Dependency { name: normalize(&name), specifiers, conditional: requirement.contains(';') })
+        })
+        .collect()
+}
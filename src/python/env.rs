@@ -0,0 +1,279 @@
+use std::{env, fs, path::{Path, PathBuf}, process::Command};
+
+use pie::{run_cmd_with, CmdOutcome, PieError, Result};
+use serde::{Deserialize, Serialize};
+
+use super::{interpreters::PythonPin, pep440::Version, venv};
+
+/// The tools pie can make a project's environment with, set with `env_backend` in project.json,
+/// or for every project in pie's config.json.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum EnvKind {
+    #[default]
+    Venv,
+    Virtualenv,
+    Uv,
+    Conda,
+}
+
+    impl EnvKind {
+        pub fn backend(self) -> Box<dyn EnvBackend> {
+            match self {
+                EnvKind::Venv => Box::new(Venv),
+                EnvKind::Virtualenv => Box::new(Virtualenv),
+                EnvKind::Uv => Box::new(Uv),
+                EnvKind::Conda => Box::new(Conda),
+            }
+        }
+    }
+
+/// Creates a project's environment, and finds and runs things inside it. The defaults are
+/// those of a venv. Every backend keeps site-packages where a venv does, so installed
+/// distributions are read the same way for all of them.
+pub trait EnvBackend {
+    /// The name used in project.json and config.json.
+    fn name(&self) -> &'static str;
+
+    /// The command creating an environment at `env`, with the python `pin` asks for, or else the default one.
+    fn create_command(&self, env: &Path, pin: Option<&PythonPin>) -> Result<Command>;
+
+    /// Creates an environment at `env` with `create_command`.
+    fn create(&self, env: &Path, pin: Option<&PythonPin>) -> Result<()> {
+        let mut command = self.create_command(env, pin)?;
+        let program = command.get_program().to_string_lossy().into_owned();
+        match run_cmd_with(&mut command, false) {
+            CmdOutcome::SpawnFailed(e) => Err(self.missing(&program, e)),
+            outcome if !outcome.success() => {
+                let args = command.get_args().map(|arg| arg.to_string_lossy()).collect::<Vec<_>>().join(" ");
+                Err(PieError::Venv(format!("'{} {}' failed.", program, args)))
+            },
+            _ => Ok(()),
+        }
+    }
+
+    /// The error for `program`, run to create an environment, not being found.
+    fn missing(&self, program: &str, e: std::io::Error) -> PieError {
+        PieError::Venv(format!("Could not run '{}': {}. Install it, or pick another 'env_backend'.", program, e))
+    }
+
+    /// The folders holding the environment's executables, in the order they go on PATH.
+    fn bin_dirs(&self, env: &Path) -> Vec<PathBuf> {
+        vec![env.join(if cfg!(windows) {"Scripts"} else {"bin"})]
+    }
+
+    /// The environment's python.
+    fn python(&self, env: &Path) -> PathBuf {
+        env.join(if cfg!(windows) {"Scripts"} else {"bin"}).join(if cfg!(windows) {"python"} else {"python3"})
+    }
+
+    /// A command running pip in the environment, with `args`.
+    fn pip(&self, env: &Path, args: &[String]) -> Command {
+        let mut command = Command::new(self.python(env));
+        command.args(["-m", "pip"]).args(args);
+        command
+    }
+
+    /// The full version of the environment's python, e.g. `3.11.4`.
+    fn python_version(&self, env: &Path) -> Option<String> {
+        venv::python_full_version(env)
+    }
+
+    /// Makes `command` run as if the environment was activated.
+    fn activate(&self, env: &Path, command: &mut Command) -> Result<()> {
+        command.env("PATH", path_with(self.bin_dirs(env))?).env("VIRTUAL_ENV", env);
+        Ok(())
+    }
+}
+
+/// The standard library's `venv`.
+pub struct Venv;
+
+    impl EnvBackend for Venv {
+        fn name(&self) -> &'static str {
+            "venv"
+        }
+
+        fn create_command(&self, env: &Path, pin: Option<&PythonPin>) -> Result<Command> {
+            let mut command = Command::new(interpreter(pin)?);
+            command.args(["-m".as_ref(), "venv".as_ref(), env.as_os_str()]);
+            Ok(command)
+        }
+
+        fn missing(&self, program: &str, e: std::io::Error) -> PieError {
+            PieError::MissingInterpreter(format!("'{}': {}", program, e))
+        }
+    }
+
+/// virtualenv, which has to be installed and on PATH.
+pub struct Virtualenv;
+
+    impl EnvBackend for Virtualenv {
+        fn name(&self) -> &'static str {
+            "virtualenv"
+        }
+
+        fn create_command(&self, env: &Path, pin: Option<&PythonPin>) -> Result<Command> {
+            let mut command = Command::new("virtualenv");
+            command.arg("--python").arg(interpreter(pin)?).arg(env);
+            Ok(command)
+        }
+    }
+
+/// uv. Its environments come without pip, packages are managed with `uv pip` instead.
+pub struct Uv;
+
+    impl EnvBackend for Uv {
+        fn name(&self) -> &'static str {
+            "uv"
+        }
+
+        /// uv understands version specifiers itself, and can download a python that satisfies them.
+        fn create_command(&self, env: &Path, pin: Option<&PythonPin>) -> Result<Command> {
+            let mut command = Command::new("uv");
+            command.args(["venv", "--quiet"]);
+            if let Some(pin) = pin {
+                command.arg("--python").arg(pin.to_string());
+            }
+            command.arg(env);
+            Ok(command)
+        }
+
+        fn pip(&self, env: &Path, args: &[String]) -> Command {
+            // `uv pip uninstall` never asks for confirmation, and rejects pip's flag to skip it.
+            let uninstall = args.first().is_some_and(|arg| arg == "uninstall");
+            let mut command = Command::new("uv");
+            command.arg("pip")
+                .args(args.iter().filter(|arg| !(uninstall && matches!(arg.as_str(), "-y" | "--yes"))))
+                .env("VIRTUAL_ENV", env);
+            command
+        }
+    }
+
+/// A conda environment, made with `conda create --prefix`.
+pub struct Conda;
+
+    impl EnvBackend for Conda {
+        fn name(&self) -> &'static str {
+            "conda"
+        }
+
+        /// Conda installs python itself, so a pinned path only says which version to install.
+        fn create_command(&self, env: &Path, pin: Option<&PythonPin>) -> Result<Command> {
+            let python = match pin {
+                None => String::from("python"),
+                Some(pin @ PythonPin::Path(_)) => format!("python={}", pin.resolve()?.version),
+                Some(PythonPin::Version { text, .. }) if text.parse::<Version>().is_ok() => format!("python={}", text),
+                Some(PythonPin::Version { text, .. }) => format!("python{}", text),
+            };
+            let mut command = Command::new("conda");
+            command.args(["create", "--yes", "--quiet", "--prefix"]).arg(env).args([&python, "pip"]);
+            Ok(command)
+        }
+
+        fn bin_dirs(&self, env: &Path) -> Vec<PathBuf> {
+            if cfg!(windows) {
+                vec![env.to_owned(), env.join("Scripts"), env.join("Library").join("bin")]
+            } else {
+                vec![env.join("bin")]
+            }
+        }
+
+        fn python(&self, env: &Path) -> PathBuf {
+            if cfg!(windows) { env.join("python") } else { env.join("bin").join("python3") }
+        }
+
+        /// Conda records every package it installs in conda-meta, as `<name>-<version>-<build>.json`.
+        fn python_version(&self, env: &Path) -> Option<String> {
+            fs::read_dir(env.join("conda-meta")).ok()?
+                .filter_map(|entry| entry.ok())
+                .find_map(|entry| {
+                    let file_name = entry.file_name().to_string_lossy().into_owned();
+                    let version = file_name.strip_prefix("python-")?.split('-').next()?;
+                    version.starts_with(|c: char| c.is_ascii_digit()).then(|| version.to_string())
+                })
+        }
+
+        fn activate(&self, env: &Path, command: &mut Command) -> Result<()> {
+            command.env("PATH", path_with(self.bin_dirs(env))?).env("CONDA_PREFIX", env);
+            Ok(())
+        }
+    }
+
+/// The python to make an environment with: the interpreter `pin` picks, or else plain `python3` from PATH.
+fn interpreter(pin: Option<&PythonPin>) -> Result<PathBuf> {
+    match pin {
+        Some(pin) => Ok(pin.resolve()?.path),
+        None => Ok(PathBuf::from(if cfg!(windows) {"python"} else {"python3"})),
+    }
+}
+
+/// PATH with `dirs` in front.
+fn path_with(mut dirs: Vec<PathBuf>) -> Result<std::ffi::OsString> {
+    if let Some(path) = env::var_os("PATH") {
+        dirs.extend(env::split_paths(&path));
+    }
+    Ok(env::join_paths(dirs)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsStr;
+
+    use super::*;
+
+    /// The program and arguments of `command`.
+    fn argv(command: &Command) -> Vec<String> {
+        [command.get_program()].into_iter().chain(command.get_args()).map(|arg| arg.to_string_lossy().into_owned()).collect()
+    }
+
+    fn create(kind: EnvKind, pin: Option<&str>) -> Vec<String> {
+        let pin = pin.map(|pin| pin.parse::<PythonPin>().unwrap());
+        argv(&kind.backend().create_command(Path::new("/p/venv"), pin.as_ref()).unwrap())
+    }
+
+    #[test]
+    fn create_commands() {
+        let python = if cfg!(windows) {"python"} else {"python3"};
+        assert_eq!(create(EnvKind::Venv, None), [python, "-m", "venv", "/p/venv"]);
+        assert_eq!(create(EnvKind::Virtualenv, None), ["virtualenv", "--python", python, "/p/venv"]);
+        assert_eq!(create(EnvKind::Uv, None), ["uv", "venv", "--quiet", "/p/venv"]);
+        assert_eq!(create(EnvKind::Uv, Some(">=3.10,<3.13")), ["uv", "venv", "--quiet", "--python", ">=3.10,<3.13", "/p/venv"]);
+        assert_eq!(create(EnvKind::Conda, None), ["conda", "create", "--yes", "--quiet", "--prefix", "/p/venv", "python", "pip"]);
+        assert_eq!(create(EnvKind::Conda, Some("3.11")), ["conda", "create", "--yes", "--quiet", "--prefix", "/p/venv", "python=3.11", "pip"]);
+        assert_eq!(create(EnvKind::Conda, Some(">=3.10")), ["conda", "create", "--yes", "--quiet", "--prefix", "/p/venv", "python>=3.10", "pip"]);
+    }
+
+    #[test]
+    fn pip_commands() {
+        let env = Path::new("/p/venv");
+        let args = ["uninstall", "-y", "flask"].map(String::from);
+        let python = EnvKind::Venv.backend().python(env).to_string_lossy().into_owned();
+
+        for kind in [EnvKind::Venv, EnvKind::Virtualenv] {
+            assert_eq!(argv(&kind.backend().pip(env, &args)), [python.as_str(), "-m", "pip", "uninstall", "-y", "flask"]);
+        }
+        let conda_python = EnvKind::Conda.backend().python(env).to_string_lossy().into_owned();
+        assert_eq!(argv(&EnvKind::Conda.backend().pip(env, &args)), [conda_python.as_str(), "-m", "pip", "uninstall", "-y", "flask"]);
+
+        let uv = EnvKind::Uv.backend().pip(env, &args);
+        assert_eq!(argv(&uv), ["uv", "pip", "uninstall", "flask"]);
+        assert!(uv.get_envs().any(|(key, value)| key == "VIRTUAL_ENV" && value == Some(env.as_os_str())));
+        assert_eq!(argv(&EnvKind::Uv.backend().pip(env, &["install".to_string(), "-y".to_string()])), ["uv", "pip", "install", "-y"]);
+    }
+
+    #[test]
+    fn activation_sets_the_environment_variable_of_the_backend() {
+        let env = Path::new("/p/venv");
+        for (kind, variable) in [(EnvKind::Venv, "VIRTUAL_ENV"), (EnvKind::Virtualenv, "VIRTUAL_ENV"), (EnvKind::Uv, "VIRTUAL_ENV"), (EnvKind::Conda, "CONDA_PREFIX")] {
+            let backend = kind.backend();
+            let mut command = Command::new("python");
+            backend.activate(env, &mut command).unwrap();
+
+            let vars = command.get_envs().collect::<Vec<_>>();
+            assert!(vars.contains(&(OsStr::new(variable), Some(env.as_os_str()))), "{:?}", kind);
+            let path = vars.iter().find(|(key, _)| *key == "PATH").and_then(|(_, path)| *path).unwrap();
+            assert_eq!(env::split_paths(path).next(), backend.bin_dirs(env).first().cloned(), "{:?}", kind);
+        }
+    }
+}
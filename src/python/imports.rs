@@ -0,0 +1,248 @@
+use std::{collections::BTreeSet, fs, io, path::Path};
+
+use pie::Result;
+use walkdir::{DirEntry, WalkDir};
+
+use super::stdlib::is_stdlib;
+
+/// The modules imported by the python files in a folder, and the modules the folder
+/// itself provides. Found by reading the source, so nothing has to be installed or run.
+#[derive(Debug, Default)]
+pub struct Scan {
+    pub imports: BTreeSet<String>,
+    pub local: BTreeSet<String>,
+}
+
+    impl Scan {
+        /// Imported modules that are neither in the standard library of python `version` nor local.
+        pub fn third_party(&self, version: (u32, u32)) -> impl Iterator<Item = &String> {
+            self.imports.iter().filter(move |module| !is_stdlib(module, version) && !self.local.contains(*module))
+        }
+    }
+
+/// Scans every .py file under `dir`, skipping venvs, conda environments, caches and hidden folders.
+pub fn scan(dir: &Path) -> Result<Scan> {
+    let mut scan = Scan::default();
+
+    for entry in WalkDir::new(dir).into_iter().filter_entry(|entry| entry.depth() == 0 || !is_ignored(entry)) {
+        let entry = entry.map_err(io::Error::from)?;
+        let path = entry.path();
+        if !entry.file_type().is_file() || path.extension().is_none_or(|ext| ext != "py") {
+            continue;
+        }
+
+        if let Some(stem) = path.file_stem() {
+            scan.local.insert(stem.to_string_lossy().into_owned());
+        }
+        // Every folder between `dir` and the file is a package the project can import.
+        for package in path.strip_prefix(dir).unwrap_or(path).parent().into_iter().flat_map(Path::components) {
+            scan.local.insert(package.as_os_str().to_string_lossy().into_owned());
+        }
+
+        let source = String::from_utf8_lossy(&fs::read(path)?).into_owned();
+        scan.imports.extend(imports_in(&source));
+    }
+
+    Ok(scan)
+}
+
+fn is_ignored(entry: &DirEntry) -> bool {
+    let name = entry.file_name().to_string_lossy();
+    entry.file_type().is_dir() && (
+        name.starts_with('.')
+        || matches!(name.as_ref(), "__pycache__" | "site-packages" | "node_modules")
+        || entry.path().join("pyvenv.cfg").exists()
+        || entry.path().join("conda-meta").is_dir()
+    )
+}
+
+/// Top level names of the absolute imports in python source, in order, including imports
+/// nested in functions, conditionals and `try` blocks. Relative imports are local, so they are skipped.
+pub fn imports_in(source: &str) -> Vec<String> {
+    let mut imports = vec![];
+    for statement in statements(source) {
+        collect(&statement, &mut imports);
+    }
+    imports
+}
+
+fn collect(statement: &str, imports: &mut Vec<String>) {
+    let statement = statement.trim();
+
+    // `try: import ujson as json`, `if TYPE_CHECKING: from x import y`
+    if let Some(body) = compound_body(statement) {
+        return collect(body, imports);
+    }
+
+    if let Some(rest) = strip_keyword(statement, "import") {
+        for module in rest.split(',') {
+            if let Some(name) = module.split_whitespace().next().and_then(top_level) {
+                imports.push(name);
+            }
+        }
+    } else if let Some(rest) = strip_keyword(statement, "from") {
+        let mut words = rest.split_whitespace();
+        if let (Some(module), Some("import")) = (words.next(), words.next()) {
+            if let Some(name) = top_level(module) {
+                imports.push(name);
+            }
+        }
+    }
+}
+
+/// The statement after the colon of a one line compound statement.
+fn compound_body(statement: &str) -> Option<&str> {
+    const KEYWORDS: [&str; 11] = ["if", "elif", "else", "try", "except", "finally", "with", "for", "while", "def", "class"];
+    KEYWORDS.iter().find_map(|keyword| strip_keyword(statement, keyword))?;
+
+    let mut depth = 0;
+    for (i, c) in statement.char_indices() {
+        match c {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth -= 1,
+            ':' if depth == 0 => return Some(&statement[i + 1..]),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn strip_keyword<'a>(statement: &'a str, keyword: &str) -> Option<&'a str> {
+    let rest = statement.strip_prefix(keyword)?;
+    match rest.chars().next() {
+        None => Some(rest),
+        Some(c) if c.is_whitespace() || c == ':' || c == '(' => Some(rest),
+        _ => None,
+    }
+}
+
+/// `a.b.c` -> `a`, or None for relative imports and anything that isn't a dotted name.
+fn top_level(module: &str) -> Option<String> {
+    let name = module.split('.').next()?;
+    let mut chars = name.chars();
+    let valid = chars.next().is_some_and(|c| c.is_alphabetic() || c == '_') && chars.all(|c| c.is_alphanumeric() || c == '_');
+    valid.then(|| name.to_string())
+}
+
+/// Splits python source into logical lines: comments are dropped, string literals are
+/// emptied, and lines are joined inside brackets and after a backslash.
+fn statements(source: &str) -> Vec<String> {
+    let chars = source.chars().collect::<Vec<char>>();
+    let mut statements = vec![];
+    let mut current = String::new();
+    let mut depth = 0usize;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '#' => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+                continue;
+            },
+            '\'' | '"' => {
+                i = skip_string(&chars, i);
+                current.push_str("\"\"");
+                continue;
+            },
+            '\\' if matches!(chars.get(i + 1), Some('\n') | Some('\r')) => {
+                i += if chars.get(i + 1) == Some(&'\r') { 3 } else { 2 };
+                current.push(' ');
+                continue;
+            },
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth = depth.saturating_sub(1),
+            '\n' | ';' if depth == 0 => {
+                statements.push(std::mem::take(&mut current));
+                i += 1;
+                continue;
+            },
+            '\n' => {
+                current.push(' ');
+                i += 1;
+                continue;
+            },
+            _ => {}
+        }
+        current.push(c);
+        i += 1;
+    }
+    statements.push(current);
+
+    statements
+}
+
+/// Index right after the string literal opening at `start`.
+fn skip_string(chars: &[char], start: usize) -> usize {
+    let quote = chars[start];
+    let triple = chars.get(start + 1) == Some(&quote) && chars.get(start + 2) == Some(&quote);
+    let mut i = start + if triple { 3 } else { 1 };
+
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            c if c == quote && !triple => return i + 1,
+            c if c == quote && chars.get(i + 1) == Some(&quote) && chars.get(i + 2) == Some(&quote) => return i + 3,
+            // An unterminated single quoted string ends with its line.
+            '\n' if !triple => return i,
+            _ => {}
+        }
+        i += 1;
+    }
+    i
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_and_from_imports() {
+        assert_eq!(imports_in("import os, sys as system\nimport yaml.loader\nfrom cv2 import imread\n"), ["os", "sys", "yaml", "cv2"]);
+    }
+
+    #[test]
+    fn relative_imports_are_skipped() {
+        assert_eq!(imports_in("from . import utils\nfrom .models import User\nfrom ..core import x\n"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn multi_line_imports() {
+        let source = "from requests import (\n    get,\n    post,\n)\nimport numpy, \\\n    pandas\nimport flask\r\n";
+        assert_eq!(imports_in(source), ["requests", "numpy", "pandas", "flask"]);
+    }
+
+    #[test]
+    fn conditional_and_nested_imports() {
+        let source = "try:\n    import ujson as json\nexcept ImportError:\n    import json\nif TYPE_CHECKING: from attr import define\ndef f():\n    import bs4; import lxml\n";
+        assert_eq!(imports_in(source), ["ujson", "json", "attr", "bs4", "lxml"]);
+    }
+
+    #[test]
+    fn strings_and_comments_are_not_imports() {
+        let source = "\"\"\"\nimport docstring\n\"\"\"\nx = 'import quoted'  # import commented\ns = \"from a import b\"\nimport real\n";
+        assert_eq!(imports_in(source), ["real"]);
+    }
+
+    #[test]
+    fn names_that_only_start_like_keywords() {
+        assert_eq!(imports_in("imported = 1\nfromage = 2\nimport_module('x')\n"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn installed_and_cached_packages_are_not_scanned() {
+        let dir = std::env::temp_dir().join(format!("pie-scan-{}", std::process::id()));
+        for (file, source) in [("main.py", "import requests\n"), ("site-packages/six.py", "import vendored\n"), ("node_modules/x/y.py", "import npm\n"), ("__pycache__/z.py", "import cached\n")] {
+            fs::create_dir_all(dir.join(file).parent().unwrap()).unwrap();
+            fs::write(dir.join(file), source).unwrap();
+        }
+        let scan = scan(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+
+        let scan = scan.unwrap();
+        assert_eq!(scan.imports.into_iter().collect::<Vec<String>>(), ["requests"]);
+        assert!(!scan.local.contains("six"));
+    }
+}
@@ -0,0 +1,221 @@
+use std::{collections::BTreeMap, fs, path::{Path, PathBuf}, process::Command};
+
+use pie::{PieError, Result};
+use serde_json::Value;
+
+use super::{dists::normalize, pep440::Version};
+
+/// Fetches simple index pages with the venv's python, so proxies and certificates are
+/// handled the way pip handles them. Prints `{url: [content type, body] | null}`, null for a 404.
+const FETCH: &str = r#"
+import json, sys, urllib.error, urllib.request
+pages = {}
+for url in sys.argv[1:]:
+    request = urllib.request.Request(url, headers={"Accept": "application/vnd.pypi.simple.v1+json, text/html;q=0.1"})
+    try:
+        with urllib.request.urlopen(request, timeout=30) as response:
+            pages[url] = [response.headers.get_content_type(), response.read().decode("utf-8", "replace")]
+    except urllib.error.HTTPError as e:
+        if e.code != 404:
+            sys.exit(f"{url}: {e}")
+        pages[url] = None
+    except Exception as e:
+        sys.exit(f"{url}: {e}")
+json.dump(pages, sys.stdout)
+"#;
+
+/// A PEP 503 (html) or PEP 691 (json) simple repository to look up versions in. A local
+/// folder can hold either a simple index tree or, like pip's --find-links, plain archives.
+pub enum Index {
+    Url(String),
+    Dir(PathBuf),
+}
+
+    impl Index {
+        pub fn new(location: &str) -> Index {
+            if location.starts_with("http://") || location.starts_with("https://") {
+                return Index::Url(location.trim_end_matches('/').to_string());
+            }
+            Index::Dir(PathBuf::from(location.strip_prefix("file://").unwrap_or(location)))
+        }
+
+        /// The versions available for each of `names` that isn't yanked. Names the index
+        /// doesn't know are left out. `python` is used to fetch pages over http(s).
+        pub fn versions(&self, names: &[String], python: &Path) -> Result<BTreeMap<String, Vec<Version>>> {
+            let pages = match self {
+                Index::Url(url) => fetch(names.iter().map(|name| format!("{}/{}/", url, normalize(name))).collect(), python)?,
+                Index::Dir(dir) => names.iter().map(|name| read_dir_page(dir, &normalize(name))).collect::<Result<Vec<Option<Page>>>>()?,
+            };
+
+            Ok(names.iter().zip(pages)
+                .filter_map(|(name, page)| {
+                    let mut versions = page?.into_iter()
+                        .filter(|(_, yanked)| !yanked)
+                        .filter_map(|(file, _)| version_of(&file, &normalize(name)))
+                        .collect::<Vec<Version>>();
+                    versions.sort();
+                    versions.dedup();
+                    Some((name.to_owned(), versions))
+                })
+                .collect())
+        }
+    }
+
+/// The files on a project page, with whether they were yanked (PEP 592).
+type Page = Vec<(String, bool)>;
+
+fn fetch(urls: Vec<String>, python: &Path) -> Result<Vec<Option<Page>>> {
+    let output = Command::new(python).arg("-c").arg(FETCH).args(&urls).output()
+        .map_err(|e| PieError::MissingInterpreter(format!("'{}': {}", python.display(), e)))?;
+    if !output.status.success() {
+        return Err(PieError::Subprocess(format!("Could not read the package index: {}", String::from_utf8_lossy(&output.stderr).trim())));
+    }
+
+    let mut pages = serde_json::from_slice::<BTreeMap<String, Option<(String, String)>>>(&output.stdout)
+        .map_err(|e| PieError::Failed(format!("Could not read the package index's pages: {}", e)))?;
+    Ok(urls.iter().map(|url| pages.remove(url).flatten().map(|(content_type, body)| parse_page(&content_type, &body))).collect())
+}
+
+/// `dir/<name>/` holding an index.json, an index.html or the archives themselves, or else
+/// a flat folder of archives of every project.
+fn read_dir_page(dir: &Path, name: &str) -> Result<Option<Page>> {
+    let project = dir.join(name);
+    if !project.is_dir() {
+        return Ok(Some(file_names(dir)?));
+    }
+
+    for (file, content_type) in [("index.json", "application/vnd.pypi.simple.v1+json"), ("index.html", "text/html")] {
+        if let Ok(body) = fs::read_to_string(project.join(file)) {
+            return Ok(Some(parse_page(content_type, &body)));
+        }
+    }
+    Ok(Some(file_names(&project)?))
+}
+
+fn file_names(dir: &Path) -> Result<Page> {
+    let mut files = vec![];
+    for entry in fs::read_dir(dir)? {
+        files.push((entry?.file_name().to_string_lossy().into_owned(), false));
+    }
+    Ok(files)
+}
+
+fn parse_page(content_type: &str, body: &str) -> Page {
+    if content_type.contains("json") {
+        let page = serde_json::from_str::<Value>(body).unwrap_or_default();
+        return page.get("files").and_then(Value::as_array).into_iter().flatten()
+            .filter_map(|file| {
+                let yanked = match file.get("yanked") {
+                    Some(Value::Bool(yanked)) => *yanked,
+                    Some(Value::String(_)) => true,
+                    _ => false,
+                };
+                Some((file.get("filename")?.as_str()?.to_string(), yanked))
+            })
+            .collect();
+    }
+
+    // PEP 503: one anchor per file, its text being the file name.
+    body.split("<a ").skip(1)
+        .filter_map(|anchor| {
+            let (attributes, rest) = anchor.split_once('>')?;
+            let (text, _) = rest.split_once("</a>")?;
+            Some((text.trim().to_string(), attributes.contains("data-yanked")))
+        })
+        .collect()
+}
+
+/// The version in a wheel, egg or sdist file name of the distribution `name` (normalized).
+fn version_of(file: &str, name: &str) -> Option<Version> {
+    let stem = [".whl", ".egg", ".tar.gz", ".tar.bz2", ".tgz", ".zip"].iter().find_map(|ext| file.strip_suffix(ext))?;
+
+    if file.ends_with(".whl") || file.ends_with(".egg") {
+        let mut parts = stem.split('-');
+        if normalize(parts.next()?) != name {
+            return None;
+        }
+        return parts.next()?.parse().ok();
+    }
+
+    // An sdist's name can contain dashes too, so try every split.
+    stem.match_indices('-')
+        .find(|(i, _)| normalize(&stem[..*i]) == name)
+        .and_then(|(i, _)| stem[i + 1..].parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn html_page() {
+        let body = r#"<!DOCTYPE html>
+<html><body>
+<h1>Links for flask</h1>
+<a href="https://files.example.com/Flask-2.0.1-py3-none-any.whl#sha256=ab">Flask-2.0.1-py3-none-any.whl</a><br/>
+<a href="https://files.example.com/Flask-2.0.1.tar.gz#sha256=cd" data-requires-python="&gt;=3.6">Flask-2.0.1.tar.gz</a><br/>
+<a href="https://files.example.com/Flask-2.0.2.tar.gz" data-yanked="broken">
+  Flask-2.0.2.tar.gz
+</a><br/>
+</body></html>"#;
+        assert_eq!(parse_page("text/html", body), [
+            (String::from("Flask-2.0.1-py3-none-any.whl"), false),
+            (String::from("Flask-2.0.1.tar.gz"), false),
+            (String::from("Flask-2.0.2.tar.gz"), true),
+        ]);
+    }
+
+    #[test]
+    fn json_page() {
+        let body = r#"{
+            "meta": {"api-version": "1.1"},
+            "name": "flask",
+            "files": [
+                {"filename": "Flask-2.0.1-py3-none-any.whl", "url": "https://files.example.com/a", "hashes": {}},
+                {"filename": "Flask-2.0.2-py3-none-any.whl", "url": "https://files.example.com/b", "hashes": {}, "yanked": "broken"},
+                {"filename": "Flask-2.0.3-py3-none-any.whl", "url": "https://files.example.com/c", "hashes": {}, "yanked": true},
+                {"filename": "Flask-2.1.0.tar.gz", "url": "https://files.example.com/d", "hashes": {}, "yanked": false}
+            ]
+        }"#;
+        assert_eq!(parse_page("application/vnd.pypi.simple.v1+json", body), [
+            (String::from("Flask-2.0.1-py3-none-any.whl"), false),
+            (String::from("Flask-2.0.2-py3-none-any.whl"), true),
+            (String::from("Flask-2.0.3-py3-none-any.whl"), true),
+            (String::from("Flask-2.1.0.tar.gz"), false),
+        ]);
+        assert!(parse_page("application/json", "not json").is_empty());
+    }
+
+    #[test]
+    fn versions_of_file_names() {
+        assert_eq!(version_of("Flask-2.0.1-py3-none-any.whl", "flask"), "2.0.1".parse().ok());
+        assert_eq!(version_of("zope.interface-6.0.tar.gz", "zope-interface"), "6.0".parse().ok());
+        assert_eq!(version_of("typing-extensions-4.8.0.zip", "typing-extensions"), "4.8.0".parse().ok());
+        assert_eq!(version_of("Flask-Login-0.6.3-py3-none-any.whl", "flask"), None);
+        assert_eq!(version_of("flask-2.0.1.exe", "flask"), None);
+    }
+
+    #[test]
+    fn local_folder_index() {
+        let dir = std::env::temp_dir().join(format!("pie-index-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        // A simple index tree with an html page, one with a json page, one of plain archives,
+        // and archives of projects without a folder of their own.
+        fs::create_dir_all(dir.join("flask")).unwrap();
+        fs::write(dir.join("flask/index.html"), r#"<a href="Flask-2.0.1.tar.gz">Flask-2.0.1.tar.gz</a><a href="Flask-3.0.0.tar.gz" data-yanked="">Flask-3.0.0.tar.gz</a>"#).unwrap();
+        fs::create_dir_all(dir.join("numpy")).unwrap();
+        fs::write(dir.join("numpy/index.json"), r#"{"files": [{"filename": "numpy-1.26.4.tar.gz"}, {"filename": "numpy-1.26.0.tar.gz"}]}"#).unwrap();
+        fs::create_dir_all(dir.join("requests")).unwrap();
+        fs::write(dir.join("requests/requests-2.32.0-py3-none-any.whl"), "").unwrap();
+        fs::write(dir.join("requests/requests-2.32.0.tar.gz"), "").unwrap();
+        fs::write(dir.join("PyYAML-6.0.1.tar.gz"), "").unwrap();
+
+        let names = ["Flask", "numpy", "requests", "PyYAML", "left-pad"].map(String::from);
+        let versions = Index::new(&format!("file://{}", dir.display())).versions(&names, Path::new("python")).unwrap();
+        let versions = versions.iter().map(|(name, versions)| format!("{}: {}", name, versions.iter().map(Version::to_string).collect::<Vec<String>>().join(" "))).collect::<Vec<String>>();
+        assert_eq!(versions, ["Flask: 2.0.1", "PyYAML: 6.0.1", "left-pad: ", "numpy: 1.26.0 1.26.4", "requests: 2.32.0"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,184 @@
+use std::{collections::BTreeSet, env, fmt, fs, path::{Path, PathBuf}, process::Command, str::FromStr};
+
+use directories::BaseDirs;
+use pie::{PieError, Result};
+use serde::{Deserialize, Serialize};
+
+use super::pep440::{Specifiers, Version};
+
+/// Prints the version of the python running it and the real path of its executable.
+const PROBE: &str = "import os, platform, sys; print(platform.python_version()); print(os.path.realpath(sys.executable))";
+
+/// A python interpreter found on this machine.
+#[derive(Debug, Clone)]
+pub struct Interpreter {
+    pub path: PathBuf,
+    pub version: Version,
+}
+
+/// The python a project wants, from `python` in project.json or pyenv's `.python-version`:
+/// an interpreter's path, or version specifiers like `>=3.10,<3.13`. A bare version like `3.11` means `==3.11.*`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum PythonPin {
+    Path(PathBuf),
+    Version { text: String, specifiers: Specifiers },
+}
+
+    impl PythonPin {
+        /// The first version listed in `.python-version` in `dir`, if there is one.
+        /// Names of pyenv virtualenvs or other implementations, like `pypy3.10`, are ignored.
+        pub fn from_version_file(dir: &Path) -> Option<PythonPin> {
+            let content = fs::read_to_string(dir.join(".python-version")).ok()?;
+            let first = content.lines().map(str::trim).find(|line| !line.is_empty() && !line.starts_with('#'))?;
+            first.parse::<Version>().ok()?;
+            first.parse().ok()
+        }
+
+        /// Whether an environment made with python `version` is what the pin asks for.
+        pub fn accepts(&self, version: &Version) -> bool {
+            match self {
+                PythonPin::Path(path) => probe(path).is_none_or(|interpreter| interpreter.version == *version),
+                PythonPin::Version { specifiers, .. } => specifiers.matches(version),
+            }
+        }
+
+        /// The interpreter the pin picks: the one at its path, or the newest one found that satisfies it.
+        pub fn resolve(&self) -> Result<Interpreter> {
+            match self {
+                PythonPin::Path(path) => probe(path)
+                    .ok_or_else(|| PieError::MissingInterpreter(format!("'{}' is not a python interpreter.", path.display()))),
+                PythonPin::Version { text, specifiers } => discover().into_iter()
+                    .find(|interpreter| specifiers.matches(&interpreter.version))
+                    .ok_or_else(|| PieError::MissingInterpreter(format!("none of the pythons found satisfies '{}'. Run 'pie python list' to see them.", text))),
+            }
+        }
+    }
+
+    impl FromStr for PythonPin {
+        type Err = String;
+
+        fn from_str(s: &str) -> std::result::Result<PythonPin, String> {
+            let s = s.trim();
+            if s.contains('/') || s.contains('\\') {
+                return Ok(PythonPin::Path(PathBuf::from(s)));
+            }
+
+            let specifiers = if s.parse::<Version>().is_ok() { format!("=={}.*", s) } else { s.to_string() };
+            match specifiers.parse::<Specifiers>() {
+                Ok(specifiers) if !s.is_empty() => Ok(PythonPin::Version { text: s.to_string(), specifiers }),
+                _ => Err(format!("'{}' is neither a path to a python nor a version like '3.11' or '>=3.10,<3.13'", s)),
+            }
+        }
+    }
+
+    impl TryFrom<String> for PythonPin {
+        type Error = String;
+
+        fn try_from(s: String) -> std::result::Result<PythonPin, String> {
+            s.parse()
+        }
+    }
+
+    impl From<PythonPin> for String {
+        fn from(pin: PythonPin) -> String {
+            pin.to_string()
+        }
+    }
+
+    impl fmt::Display for PythonPin {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                PythonPin::Path(path) => write!(f, "{}", path.display()),
+                PythonPin::Version { text, .. } => f.write_str(text),
+            }
+        }
+    }
+
+/// Every python interpreter found on PATH, in pyenv (its shims and installed versions) and in
+/// the usual system folders, newest first. An interpreter reachable from several places is listed once.
+pub fn discover() -> Vec<Interpreter> {
+    let mut dirs = env::var_os("PATH").map(|path| env::split_paths(&path).collect::<Vec<PathBuf>>()).unwrap_or_default();
+
+    let pyenv_root = env::var_os("PYENV_ROOT").map(PathBuf::from)
+        .or_else(|| BaseDirs::new().map(|dirs| dirs.home_dir().join(".pyenv")));
+    if let Some(pyenv_root) = pyenv_root {
+        dirs.push(pyenv_root.join("shims"));
+        let versions = fs::read_dir(pyenv_root.join("versions")).into_iter().flatten().filter_map(|entry| entry.ok());
+        dirs.extend(versions.map(|entry| if cfg!(windows) { entry.path() } else { entry.path().join("bin") }));
+    }
+
+    if cfg!(not(windows)) {
+        dirs.extend(["/usr/bin", "/usr/local/bin", "/opt/homebrew/bin"].map(PathBuf::from));
+    }
+
+    let mut seen = BTreeSet::new();
+    let mut found = BTreeSet::new();
+    let mut interpreters = vec![];
+    for dir in dirs {
+        let mut candidates = fs::read_dir(&dir).into_iter().flatten()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| is_python(&entry.file_name().to_string_lossy()))
+            .map(|entry| entry.path())
+            .collect::<Vec<PathBuf>>();
+        candidates.sort();
+
+        for candidate in candidates {
+            // Shims and symlinks lead to the same few executables, which only need running once.
+            let canonical = fs::canonicalize(&candidate).unwrap_or_else(|_| candidate.to_owned());
+            if !seen.insert(canonical) {
+                continue;
+            }
+            if let Some(interpreter) = probe(&candidate).filter(|interpreter| found.insert(interpreter.path.to_owned())) {
+                interpreters.push(interpreter);
+            }
+        }
+    }
+
+    interpreters.sort_by(|a, b| b.version.cmp(&a.version).then_with(|| a.path.cmp(&b.path)));
+    interpreters
+}
+
+/// Runs `python` to find out its version and where it really is.
+pub fn probe(python: &Path) -> Option<Interpreter> {
+    let output = Command::new(python).args(["-c", PROBE]).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8(output.stdout).ok()?;
+    let mut lines = stdout.lines();
+    let version = lines.next()?.trim().parse().ok()?;
+    let path = PathBuf::from(lines.next()?.trim());
+    Some(Interpreter { path, version })
+}
+
+/// `python`, `python3` and `python3.X`, the names python is installed under.
+fn is_python(file_name: &str) -> bool {
+    let name = if cfg!(windows) { file_name.strip_suffix(".exe").unwrap_or(file_name) } else { file_name };
+    match name.strip_prefix("python3") {
+        Some("") => true,
+        Some(minor) => minor.strip_prefix('.').is_some_and(|minor| !minor.is_empty() && minor.chars().all(|c| c.is_ascii_digit())),
+        None => name == "python",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pins() {
+        let accepts = |pin: &str, version: &str| pin.parse::<PythonPin>().unwrap().accepts(&version.parse().unwrap());
+
+        assert!(accepts("3.11", "3.11.7") && !accepts("3.11", "3.12.0"));
+        assert!(accepts(">=3.10,<3.13", "3.12.1") && !accepts(">=3.10,<3.13", "3.13.0"));
+        assert!(matches!("/usr/bin/python3.12".parse(), Ok(PythonPin::Path(_))));
+        assert!("latest".parse::<PythonPin>().is_err());
+    }
+
+    #[test]
+    fn python_names() {
+        assert!(is_python("python") && is_python("python3") && is_python("python3.12"));
+        assert!(!is_python("python3-config") && !is_python("python3.12-config") && !is_python("python2.7") && !is_python("pythonw"));
+    }
+}
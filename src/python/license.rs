@@ -0,0 +1,72 @@
+use spdx::{Expression, ParseMode};
+
+use super::dists::{metadata_headers, Dist};
+
+/// Trove classifiers (`License :: OSI Approved :: <name>`) that name a single SPDX licence.
+/// Ambiguous ones, like `BSD License`, are left out and shown as they are.
+const CLASSIFIERS: &[(&str, &str)] = &[
+    ("Apache Software License", "Apache-2.0"),
+    ("Boost Software License 1.0 (BSL-1.0)", "BSL-1.0"),
+    ("CC0 1.0 Universal (CC0 1.0) Public Domain Dedication", "CC0-1.0"),
+    ("Eclipse Public License 2.0 (EPL-2.0)", "EPL-2.0"),
+    ("European Union Public Licence 1.2 (EUPL 1.2)", "EUPL-1.2"),
+    ("GNU Affero General Public License v3", "AGPL-3.0-only"),
+    ("GNU Affero General Public License v3 or later (AGPLv3+)", "AGPL-3.0-or-later"),
+    ("GNU General Public License v2 (GPLv2)", "GPL-2.0-only"),
+    ("GNU General Public License v2 or later (GPLv2+)", "GPL-2.0-or-later"),
+    ("GNU General Public License v3 (GPLv3)", "GPL-3.0-only"),
+    ("GNU General Public License v3 or later (GPLv3+)", "GPL-3.0-or-later"),
+    ("GNU Lesser General Public License v2 (LGPLv2)", "LGPL-2.0-only"),
+    ("GNU Lesser General Public License v2 or later (LGPLv2+)", "LGPL-2.0-or-later"),
+    ("GNU Lesser General Public License v3 (LGPLv3)", "LGPL-3.0-only"),
+    ("GNU Lesser General Public License v3 or later (LGPLv3+)", "LGPL-3.0-or-later"),
+    ("Historical Permission Notice and Disclaimer (HPND)", "HPND"),
+    ("ISC License (ISCL)", "ISC"),
+    ("MIT License", "MIT"),
+    ("MIT No Attribution License (MIT-0)", "MIT-0"),
+    ("Mozilla Public License 2.0 (MPL 2.0)", "MPL-2.0"),
+    ("Python Software Foundation License", "PSF-2.0"),
+    ("The Unlicense (Unlicense)", "Unlicense"),
+    ("Universal Permissive License (UPL)", "UPL-1.0"),
+    ("Zope Public License", "ZPL-2.1"),
+];
+
+/// The licence a distribution declares: `text` to show, and the SPDX expression it
+/// amounts to when that is known.
+pub struct License {
+    pub text: String,
+    pub expression: Option<Expression>,
+}
+
+/// Reads a distribution's licence from, in order, `License-Expression` (PEP 639), a `License`
+/// that is an SPDX expression or a common spelling of one, or its licence classifiers.
+pub fn license_of(dist: &Dist) -> License {
+    let expression = metadata_headers(dist, "License-Expression").into_iter().next();
+    let license = metadata_headers(dist, "License").into_iter().next().filter(|license| !license.is_empty() && license != "UNKNOWN");
+    let classifiers = metadata_headers(dist, "Classifier").into_iter()
+        .filter_map(|classifier| classifier.strip_prefix("License ::").map(|name| name.rsplit("::").next().unwrap_or(name).trim().to_string()))
+        .filter(|name| name != "OSI Approved")
+        .collect::<Vec<String>>();
+
+    for candidate in expression.iter().chain(&license) {
+        // Spellings like `apache 2.0` or `GPLv3+` are rewritten to the SPDX ids they stand for.
+        let canonical = match Expression::canonicalize(candidate) {
+            Ok(canonical) => canonical.unwrap_or_else(|| candidate.to_owned()),
+            Err(_) => continue,
+        };
+        if let Ok(parsed) = Expression::parse_mode(&canonical, ParseMode::LAX) {
+            return License { text: canonical, expression: Some(parsed) };
+        }
+    }
+
+    // Several licence classifiers mean the package can be used under any of them.
+    let spdx = classifiers.iter().map(|name| CLASSIFIERS.iter().find(|(classifier, _)| classifier == name).map(|(_, id)| *id)).collect::<Option<Vec<&str>>>();
+    if let Some(ids) = spdx.filter(|ids| !ids.is_empty()) {
+        if let Ok(parsed) = Expression::parse(&ids.join(" OR ")) {
+            return License { text: parsed.to_string(), expression: Some(parsed) };
+        }
+    }
+
+    let text = license.or_else(|| (!classifiers.is_empty()).then(|| classifiers.join(", "))).unwrap_or_default();
+    License { text, expression: None }
+}
@@ -0,0 +1,130 @@
+use std::{collections::{BTreeMap, BTreeSet}, fs::{self, File}, io, path::Path};
+
+use pie::{PieError, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use super::{dists::{self, closure, normalize, Dist}, requirements::requirement_name};
+
+/// Distributions every venv comes with, which pip manages itself. They are never locked or removed.
+const UNLOCKED: [&str; 4] = ["pip", "setuptools", "wheel", "distribute"];
+
+/// pie.lock: the exact distributions installed in a project's venv.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Lock {
+    pub python: Option<String>,
+    pub packages: Vec<LockedPackage>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LockedPackage {
+    pub name: String,
+    pub version: String,
+
+    /// sha256 of the wheel or sdist it was installed from, when that is known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+
+    /// The dependency groups it is only installed for. Empty for runtime packages.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<String>,
+}
+
+    impl Lock {
+        /// Locks what is installed in `venv`, whose python is at version `python`. A package only needed by the requirements of some
+        /// dependency `groups`, and not by the `runtime` requirements, is locked as part of those groups.
+        pub fn from_venv(venv: &Path, python: Option<String>, runtime: &[String], groups: &BTreeMap<String, Vec<String>>) -> Lock {
+            let installed = dists::installed(venv);
+            let needed = |requirements: &[String]| closure(requirements.iter().filter_map(|line| requirement_name(line)), &installed);
+
+            let runtime = needed(runtime);
+            let groups = groups.iter().map(|(group, requirements)| (group, needed(requirements))).collect::<Vec<(&String, BTreeSet<String>)>>();
+
+            let packages = installed.iter()
+                .filter(|dist| is_locked(&dist.name))
+                .map(|dist| {
+                    let name = normalize(&dist.name);
+                    let groups = if runtime.contains(&name) { vec![] } else {
+                        groups.iter().filter(|(_, needed)| needed.contains(&name)).map(|(group, _)| group.to_string()).collect()
+                    };
+                    LockedPackage { name: dist.name.to_owned(), version: dist.version.to_owned(), sha256: archive_hash(dist), groups }
+                })
+                .collect();
+
+            Lock { python, packages }
+        }
+
+        pub fn read(path: &Path) -> Result<Lock> {
+            let lock = match fs::read_to_string(path) {
+                Ok(lock) => lock,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => return Err(PieError::Failed(format!("'{}' not found. Run 'pie lock' first.", path.display()))),
+                Err(e) => return Err(e.into()),
+            };
+            serde_json::from_str(&lock).map_err(|e| PieError::Failed(format!("'{}' is not a valid lock file: {}", path.display(), e)))
+        }
+
+        pub fn write(&self, path: &Path) -> Result<()> {
+            fs::write(path, serde_json::to_string_pretty(self).map_err(|e| PieError::Failed(e.to_string()))? + "\n")?;
+            Ok(())
+        }
+    }
+
+/// Whether pie locks, and so also removes, the distribution `name`.
+pub fn is_locked(name: &str) -> bool {
+    !UNLOCKED.contains(&normalize(name).as_str())
+}
+
+/// The sha256 of the archive a distribution was installed from. pip records it in direct_url.json
+/// (PEP 610) when it knows it. Otherwise, an archive installed from a local file that still exists is hashed.
+fn archive_hash(dist: &Dist) -> Option<String> {
+    let direct_url = serde_json::from_str::<Value>(&fs::read_to_string(dist.info_dir.join("direct_url.json")).ok()?).ok()?;
+    let archive_info = direct_url.get("archive_info")?;
+
+    if let Some(Value::String(sha256)) = archive_info.pointer("/hashes/sha256") {
+        return Some(sha256.to_owned());
+    }
+    if let Some(sha256) = archive_info.get("hash").and_then(Value::as_str).and_then(|hash| hash.strip_prefix("sha256=")) {
+        return Some(sha256.to_string());
+    }
+
+    let path = direct_url.get("url")?.as_str()?.strip_prefix("file://")?;
+    let path = if cfg!(windows) { path.trim_start_matches('/') } else { path };
+    hash_file(Path::new(path)).ok()
+}
+
+fn hash_file(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packages_only_groups_need_are_marked() {
+        let venv = std::env::temp_dir().join(format!("pie-lock-{}", std::process::id()));
+        let site_packages = if cfg!(windows) { venv.join("Lib").join("site-packages") } else { venv.join("lib").join("python3.12").join("site-packages") };
+        for (name, requires) in [("Flask", &["werkzeug"][..]), ("Werkzeug", &[]), ("pytest", &["iniconfig", "werkzeug"]), ("iniconfig", &[]), ("Sphinx", &[]), ("stray", &[]), ("pip", &[])] {
+            let info_dir = site_packages.join(format!("{}-1.0.dist-info", name));
+            fs::create_dir_all(&info_dir).unwrap();
+            let requires = requires.iter().map(|dependency| format!("Requires-Dist: {}\n", dependency)).collect::<String>();
+            fs::write(info_dir.join("METADATA"), format!("Metadata-Version: 2.1\nName: {}\nVersion: 1.0\n{}", name, requires)).unwrap();
+        }
+
+        let groups = BTreeMap::from([
+            (String::from("dev"), vec![String::from("pytest>=8")]),
+            (String::from("test"), vec![String::from("pytest")]),
+            (String::from("docs"), vec![String::from("sphinx")]),
+        ]);
+        let lock = Lock::from_venv(&venv, Some(String::from("3.12.1")), &[String::from("flask==3.0")], &groups);
+        fs::remove_dir_all(&venv).unwrap();
+
+        // Packages nothing asks for stay runtime packages, so sync never removes them.
+        let marked = lock.packages.iter().map(|package| format!("{}:{}", package.name, package.groups.join(","))).collect::<Vec<String>>();
+        assert_eq!(marked, ["Flask:", "iniconfig:dev,test", "pytest:dev,test", "Sphinx:docs", "stray:", "Werkzeug:"]);
+        assert_eq!(lock.python.as_deref(), Some("3.12.1"));
+    }
+}
@@ -0,0 +1,258 @@
+use std::{fmt, fs::{self, File}, io::Read, path::Path};
+
+use clap::ArgEnum;
+use pie::{PieError, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use walkdir::WalkDir;
+
+use super::{dists::normalize, pep440::Version};
+
+/// An advisory in the OSV format (https://ossf.github.io/osv-schema), keeping only what `pie audit` reads.
+#[derive(Deserialize, Debug)]
+pub struct Advisory {
+    pub id: String,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    #[serde(default)]
+    pub summary: String,
+    #[serde(default)]
+    withdrawn: Option<String>,
+    #[serde(default)]
+    severity: Vec<Severity>,
+    #[serde(default)]
+    affected: Vec<Affected>,
+    #[serde(default)]
+    database_specific: Value,
+}
+
+#[derive(Deserialize, Debug)]
+struct Severity {
+    #[serde(rename = "type")]
+    kind: String,
+    score: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct Affected {
+    package: Package,
+    #[serde(default)]
+    ranges: Vec<Range>,
+    #[serde(default)]
+    versions: Vec<String>,
+    #[serde(default)]
+    database_specific: Value,
+}
+
+#[derive(Deserialize, Debug)]
+struct Package {
+    ecosystem: String,
+    name: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct Range {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    events: Vec<Value>,
+}
+
+/// How bad an advisory is. Advisories that don't say count as low.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ArgEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+    impl fmt::Display for Level {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str(match self { Level::Low => "low", Level::Medium => "medium", Level::High => "high", Level::Critical => "critical" })
+        }
+    }
+
+    impl Advisory {
+        /// Whether `version` of the PyPI distribution `name` is affected, and if so, the versions that fix it.
+        pub fn affects(&self, name: &str, version: &Version) -> Option<Vec<String>> {
+            if self.withdrawn.is_some() {
+                return None;
+            }
+
+            let mut affected = false;
+            let mut fixed = vec![];
+            for entry in self.affected.iter().filter(|entry| entry.package.ecosystem == "PyPI" && normalize(&entry.package.name) == normalize(name)) {
+                if entry.versions.iter().any(|listed| listed.parse::<Version>().is_ok_and(|listed| listed == *version)) {
+                    affected = true;
+                }
+                for range in entry.ranges.iter().filter(|range| range.kind == "ECOSYSTEM") {
+                    if let Some(range_fixed) = range.affects(version) {
+                        affected = true;
+                        fixed.extend(range_fixed);
+                    }
+                }
+            }
+            affected.then_some(fixed)
+        }
+
+        /// The severity given by the database (GitHub's `database_specific.severity`),
+        /// or else computed from a CVSS v3 vector.
+        pub fn level(&self) -> Option<Level> {
+            let named = self.affected.iter().map(|entry| &entry.database_specific).chain([&self.database_specific])
+                .find_map(|specific| specific.get("severity").and_then(Value::as_str))
+                .and_then(|severity| match severity.to_ascii_lowercase().as_str() {
+                    "low" => Some(Level::Low),
+                    "moderate" | "medium" => Some(Level::Medium),
+                    "high" => Some(Level::High),
+                    "critical" => Some(Level::Critical),
+                    _ => None,
+                });
+
+            named.or_else(|| self.severity.iter()
+                .filter(|severity| severity.kind == "CVSS_V3")
+                .find_map(|severity| cvss3_score(&severity.score))
+                .and_then(level_of_score))
+        }
+    }
+
+    impl Range {
+        /// Whether `version` is inside the range, and if so, the `fixed` versions after it. Events
+        /// are in order: each `introduced` starts an affected span, `fixed` or `last_affected` ends it.
+        fn affects(&self, version: &Version) -> Option<Vec<String>> {
+            let mut inside = false;
+            let mut affected = false;
+            let mut fixed = vec![];
+
+            for event in &self.events {
+                let (kind, value) = match event.as_object().and_then(|event| event.iter().next()) {
+                    Some((kind, Value::String(value))) => (kind.as_str(), value.as_str()),
+                    _ => continue,
+                };
+                let at = value.parse::<Version>().ok();
+                match kind {
+                    "introduced" => inside = value == "0" || at.is_some_and(|at| *version >= at),
+                    "fixed" if inside => {
+                        if at.as_ref().is_some_and(|at| version < at) {
+                            affected = true;
+                            fixed.push(value.to_string());
+                        }
+                        inside = false;
+                    },
+                    "last_affected" if inside => {
+                        affected |= at.is_some_and(|at| *version <= at);
+                        inside = false;
+                    },
+                    _ => {}
+                }
+            }
+
+            (affected || inside).then_some(fixed)
+        }
+    }
+
+/// Reads every advisory in `db`: a folder of OSV JSON files, searched recursively, or a zip of them,
+/// like the PyPI `all.zip` published by osv.dev. Files that aren't OSV records are skipped.
+pub fn load(db: &Path) -> Result<Vec<Advisory>> {
+    let mut advisories = vec![];
+
+    if db.is_dir() {
+        for entry in WalkDir::new(db) {
+            let entry = entry.map_err(std::io::Error::from)?;
+            if entry.file_type().is_file() && entry.path().extension().is_some_and(|ext| ext == "json") {
+                advisories.extend(serde_json::from_slice(&fs::read(entry.path())?).ok());
+            }
+        }
+        return Ok(advisories);
+    }
+
+    let mut zip = zip::ZipArchive::new(File::open(db)?)
+        .map_err(|e| PieError::Failed(format!("'{}' is neither a folder nor a zip of OSV advisories: {}", db.display(), e)))?;
+    for i in 0..zip.len() {
+        let mut file = zip.by_index(i).map_err(|e| PieError::Archive(format!("'{}': {}", db.display(), e)))?;
+        if !file.name().ends_with(".json") {
+            continue;
+        }
+        let mut content = vec![];
+        file.read_to_end(&mut content)?;
+        advisories.extend(serde_json::from_slice(&content).ok());
+    }
+    Ok(advisories)
+}
+
+fn level_of_score(score: f64) -> Option<Level> {
+    match score {
+        s if s >= 9.0 => Some(Level::Critical),
+        s if s >= 7.0 => Some(Level::High),
+        s if s >= 4.0 => Some(Level::Medium),
+        s if s > 0.0 => Some(Level::Low),
+        _ => None,
+    }
+}
+
+/// The base score of a CVSS v3.0 or v3.1 vector, like `CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:H/I:H/A:H`.
+fn cvss3_score(vector: &str) -> Option<f64> {
+    let metric = |name: &str| vector.split('/').find_map(|part| part.strip_prefix(name)?.strip_prefix(':'));
+    let changed = metric("S")? == "C";
+
+    let av = match metric("AV")? { "N" => 0.85, "A" => 0.62, "L" => 0.55, "P" => 0.2, _ => return None };
+    let ac = match metric("AC")? { "L" => 0.77, "H" => 0.44, _ => return None };
+    let pr = match (metric("PR")?, changed) { ("N", _) => 0.85, ("L", false) => 0.62, ("L", true) => 0.68, ("H", false) => 0.27, ("H", true) => 0.5, _ => return None };
+    let ui = match metric("UI")? { "N" => 0.85, "R" => 0.62, _ => return None };
+    let cia = |name: &str| match metric(name)? { "H" => Some(0.56), "L" => Some(0.22), "N" => Some(0.0), _ => None };
+
+    let iss = 1.0 - (1.0 - cia("C")?) * (1.0 - cia("I")?) * (1.0 - cia("A")?);
+    let impact = if changed { 7.52 * (iss - 0.029) - 3.25 * (iss - 0.02_f64).powi(15) } else { 6.42 * iss };
+    let exploitability = 8.22 * av * ac * pr * ui;
+
+    if impact <= 0.0 {
+        return Some(0.0);
+    }
+    let score = if changed { 1.08 * (impact + exploitability) } else { impact + exploitability };
+    Some(round_up(score.min(10.0)))
+}
+
+/// CVSS 3.1's Roundup: the smallest number with one decimal that is at least `x`.
+fn round_up(x: f64) -> f64 {
+    let int = (x * 100_000.0).round() as i64;
+    if int % 10_000 == 0 { int as f64 / 100_000.0 } else { ((int / 10_000) + 1) as f64 / 10.0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn advisory(json: &str) -> Advisory {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn cvss3_base_scores() {
+        assert_eq!(cvss3_score("CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:H/I:H/A:H"), Some(9.8));
+        assert_eq!(cvss3_score("CVSS:3.1/AV:N/AC:L/PR:N/UI:R/S:C/C:L/I:L/A:N"), Some(6.1));
+        assert_eq!(cvss3_score("CVSS:3.0/AV:L/AC:H/PR:H/UI:R/S:U/C:N/I:N/A:N"), Some(0.0));
+    }
+
+    #[test]
+    fn ranges_and_listed_versions() {
+        let advisory = advisory(r#"{"id": "PYSEC-1", "affected": [{"package": {"ecosystem": "PyPI", "name": "Demo_Pkg"},
+            "ranges": [{"type": "ECOSYSTEM", "events": [{"introduced": "0"}, {"fixed": "1.2"}, {"introduced": "2.0"}, {"last_affected": "2.1"}]}],
+            "versions": ["3.0"]}]}"#);
+        let affects = |version: &str| advisory.affects("demo-pkg", &version.parse().unwrap());
+
+        assert_eq!(affects("1.1"), Some(vec![String::from("1.2")]));
+        assert_eq!(affects("1.2"), None);
+        assert_eq!(affects("2.1"), Some(vec![]));
+        assert_eq!(affects("2.2"), None);
+        assert_eq!(affects("3.0"), Some(vec![]));
+        assert_eq!(advisory.affects("other", &"1.0".parse().unwrap()), None);
+    }
+
+    #[test]
+    fn named_severity_wins_over_cvss() {
+        let advisory = advisory(r#"{"id": "GHSA-1", "severity": [{"type": "CVSS_V3", "score": "CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:H/I:H/A:H"}],
+            "database_specific": {"severity": "MODERATE"}}"#);
+        assert_eq!(advisory.level(), Some(Level::Medium));
+    }
+}
@@ -0,0 +1,353 @@
+use std::{cmp::Ordering, fmt, str::FromStr};
+
+/// A python package version, as specified by PEP 440 (https://peps.python.org/pep-0440).
+/// Non-normalized spellings like `1.0-RC.1` or `v2.0.post` are accepted.
+#[derive(Debug, Clone)]
+pub struct Version {
+    pub epoch: u64,
+    pub release: Vec<u64>,
+    pub pre: Option<(PreKind, u64)>,
+    pub post: Option<u64>,
+    pub dev: Option<u64>,
+    pub local: Vec<LocalPart>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PreKind {
+    Alpha,
+    Beta,
+    Rc,
+}
+
+/// A segment of a local version label. Numbers sort after strings.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LocalPart {
+    Str(String),
+    Num(u64),
+}
+
+    impl Version {
+        pub fn is_prerelease(&self) -> bool {
+            self.pre.is_some() || self.dev.is_some()
+        }
+
+        /// The version without its local label.
+        pub fn public(&self) -> Version {
+            Version { local: vec![], ..self.clone() }
+        }
+
+        /// The release numbers with trailing zeros removed, so `1.0` and `1` compare equal.
+        fn trimmed_release(&self) -> &[u64] {
+            let len = self.release.iter().rposition(|n| *n != 0).map_or(0, |i| i + 1);
+            &self.release[..len]
+        }
+
+        /// The release numbers padded with zeros or cut to `len`.
+        fn release_prefix(&self, len: usize) -> Vec<u64> {
+            (0..len).map(|i| self.release.get(i).copied().unwrap_or(0)).collect()
+        }
+    }
+
+    impl FromStr for Version {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Version, String> {
+            let invalid = || format!("'{}' is not a valid PEP 440 version", s);
+            let lower = s.trim().to_ascii_lowercase();
+            let mut rest = lower.strip_prefix('v').unwrap_or(&lower);
+
+            let (public, local) = match rest.split_once('+') {
+                Some((public, local)) => (public, Some(local)),
+                None => (rest, None),
+            };
+            rest = public;
+
+            let mut epoch = 0;
+            if let Some((e, after)) = rest.split_once('!') {
+                epoch = e.parse().map_err(|_| invalid())?;
+                rest = after;
+            }
+
+            let mut release = vec![];
+            loop {
+                let (number, after) = take_number(rest).ok_or_else(invalid)?;
+                release.push(number);
+                rest = after;
+                match rest.strip_prefix('.') {
+                    Some(after) if after.starts_with(|c: char| c.is_ascii_digit()) => rest = after,
+                    _ => break,
+                }
+            }
+
+            let mut pre = None;
+            let labels = [("alpha", PreKind::Alpha), ("beta", PreKind::Beta), ("preview", PreKind::Rc), ("pre", PreKind::Rc), ("rc", PreKind::Rc), ("a", PreKind::Alpha), ("b", PreKind::Beta), ("c", PreKind::Rc)];
+            if let Some((kind, after)) = labels.iter().find_map(|(label, kind)| strip_separator(rest).strip_prefix(label).map(|after| (*kind, after))) {
+                let (number, after) = optional_number(after);
+                pre = Some((kind, number));
+                rest = after;
+            }
+
+            let mut post = None;
+            if let Some(after) = rest.strip_prefix('-').filter(|after| after.starts_with(|c: char| c.is_ascii_digit())) {
+                let (number, after) = take_number(after).ok_or_else(invalid)?;
+                post = Some(number);
+                rest = after;
+            } else if let Some(after) = ["post", "rev", "r"].iter().find_map(|label| strip_separator(rest).strip_prefix(label)) {
+                let (number, after) = optional_number(after);
+                post = Some(number);
+                rest = after;
+            }
+
+            let mut dev = None;
+            if let Some(after) = strip_separator(rest).strip_prefix("dev") {
+                let (number, after) = optional_number(after);
+                dev = Some(number);
+                rest = after;
+            }
+
+            if !rest.is_empty() {
+                return Err(invalid());
+            }
+
+            let local = match local {
+                Some(local) => local.split(['.', '-', '_'])
+                    .map(|part| match part.parse() {
+                        Ok(number) => Ok(LocalPart::Num(number)),
+                        Err(_) if !part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric()) => Ok(LocalPart::Str(part.to_string())),
+                        Err(_) => Err(invalid()),
+                    })
+                    .collect::<Result<Vec<LocalPart>, String>>()?,
+                None => vec![],
+            };
+
+            Ok(Version { epoch, release, pre, post, dev, local })
+        }
+    }
+
+    impl fmt::Display for Version {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            if self.epoch != 0 {
+                write!(f, "{}!", self.epoch)?;
+            }
+            write!(f, "{}", self.release.iter().map(u64::to_string).collect::<Vec<String>>().join("."))?;
+            if let Some((kind, number)) = self.pre {
+                let label = match kind { PreKind::Alpha => "a", PreKind::Beta => "b", PreKind::Rc => "rc" };
+                write!(f, "{}{}", label, number)?;
+            }
+            if let Some(post) = self.post {
+                write!(f, ".post{}", post)?;
+            }
+            if let Some(dev) = self.dev {
+                write!(f, ".dev{}", dev)?;
+            }
+            if !self.local.is_empty() {
+                let local = self.local.iter().map(|part| match part { LocalPart::Str(s) => s.to_owned(), LocalPart::Num(n) => n.to_string() }).collect::<Vec<String>>();
+                write!(f, "+{}", local.join("."))?;
+            }
+            Ok(())
+        }
+    }
+
+    impl PartialEq for Version {
+        fn eq(&self, other: &Version) -> bool {
+            self.cmp(other) == Ordering::Equal
+        }
+    }
+
+    impl Eq for Version {}
+
+    impl PartialOrd for Version {
+        fn partial_cmp(&self, other: &Version) -> Option<Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+
+    impl Ord for Version {
+        fn cmp(&self, other: &Version) -> Ordering {
+            self.epoch.cmp(&other.epoch)
+                .then_with(|| self.trimmed_release().cmp(other.trimmed_release()))
+                .then_with(|| pre_key(self).cmp(&pre_key(other)))
+                .then_with(|| self.post.cmp(&other.post))
+                // A dev release comes before the release it is a dev release of.
+                .then_with(|| (self.dev.is_none(), self.dev).cmp(&(other.dev.is_none(), other.dev)))
+                .then_with(|| self.local.cmp(&other.local))
+        }
+    }
+
+/// Sorts `1.0.dev0` before `1.0a1`, and both before `1.0`.
+fn pre_key(version: &Version) -> (u8, u64) {
+    match version.pre {
+        Some((kind, number)) => (kind as u8 + 1, number),
+        None if version.post.is_none() && version.dev.is_some() => (0, 0),
+        None => (u8::MAX, 0),
+    }
+}
+
+fn strip_separator(s: &str) -> &str {
+    s.strip_prefix(['.', '-', '_']).unwrap_or(s)
+}
+
+fn take_number(s: &str) -> Option<(u64, &str)> {
+    let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    Some((s[..end].parse().ok()?, &s[end..]))
+}
+
+/// A number after a pre, post or dev label, which may be left out to mean 0.
+fn optional_number(s: &str) -> (u64, &str) {
+    take_number(strip_separator(s)).unwrap_or((0, s))
+}
+
+/// A comma separated list of version specifiers, like `>=1.2,!=1.3.*,<2`.
+#[derive(Debug, Clone, Default)]
+pub struct Specifiers(Vec<Specifier>);
+
+#[derive(Debug, Clone)]
+struct Specifier {
+    op: String,
+    version: Version,
+    wildcard: bool,
+    text: String,
+}
+
+    impl Specifiers {
+        /// Whether `version` satisfies every specifier. Pre-releases only do if a specifier mentions one.
+        pub fn contains(&self, version: &Version) -> bool {
+            (!version.is_prerelease() || self.allows_prereleases()) && self.matches(version)
+        }
+
+        /// Whether `version` satisfies every specifier, pre-release or not. For checking what is already installed.
+        pub fn matches(&self, version: &Version) -> bool {
+            self.0.iter().all(|specifier| specifier.contains(version))
+        }
+
+        pub fn allows_prereleases(&self) -> bool {
+            self.0.iter().any(|specifier| specifier.version.is_prerelease())
+        }
+
+        /// The single version pinned with `==`, if that is all there is.
+        pub fn pin(&self) -> Option<&str> {
+            match self.0.as_slice() {
+                [specifier] if specifier.op == "==" && !specifier.wildcard => Some(&specifier.text),
+                _ => None,
+            }
+        }
+    }
+
+    impl FromStr for Specifiers {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Specifiers, String> {
+            let s = s.trim();
+            if s.is_empty() {
+                return Ok(Specifiers::default());
+            }
+            s.split(',').map(str::parse).collect::<Result<Vec<Specifier>, String>>().map(Specifiers)
+        }
+    }
+
+    impl Specifier {
+        fn contains(&self, candidate: &Version) -> bool {
+            let spec = &self.version;
+            match self.op.as_str() {
+                "===" => candidate.to_string() == self.text,
+                "==" if self.wildcard => self.matches_prefix(candidate),
+                "!=" if self.wildcard => !self.matches_prefix(candidate),
+                // A spec without a local label matches every local version of it.
+                "==" => if spec.local.is_empty() { candidate.public() == *spec } else { candidate == spec },
+                "!=" => !(if spec.local.is_empty() { candidate.public() == *spec } else { candidate == spec }),
+                "<=" => candidate.public() <= *spec,
+                ">=" => candidate.public() >= *spec,
+                // `<1.0` excludes 1.0's pre-releases, unless it is one itself.
+                "<" => candidate.public() < *spec && (spec.is_prerelease() || !(candidate.is_prerelease() && same_release(candidate, spec))),
+                // `>1.0` excludes 1.0's post-releases, unless it is one itself.
+                ">" => candidate.public() > *spec && (spec.post.is_some() || !(candidate.post.is_some() && same_release(candidate, spec) && candidate.pre == spec.pre)),
+                "~=" => {
+                    let prefix = Version { release: spec.release[..spec.release.len() - 1].to_vec(), pre: None, post: None, dev: None, local: vec![], ..spec.clone() };
+                    candidate.public() >= *spec && prefix.release == candidate.release_prefix(prefix.release.len()) && candidate.epoch == spec.epoch
+                },
+                _ => false,
+            }
+        }
+
+        fn matches_prefix(&self, candidate: &Version) -> bool {
+            candidate.epoch == self.version.epoch && candidate.release_prefix(self.version.release.len()) == self.version.release
+        }
+    }
+
+    impl FromStr for Specifier {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Specifier, String> {
+            let s = s.trim();
+            let op = ["===", "~=", "==", "!=", "<=", ">=", "<", ">"].iter()
+                .find(|op| s.starts_with(*op))
+                .ok_or_else(|| format!("'{}' is not a version specifier", s))?;
+            let text = s[op.len()..].trim().to_string();
+            let (version, wildcard) = match text.strip_suffix(".*") {
+                Some(prefix) if matches!(*op, "==" | "!=") => (prefix, true),
+                _ => (text.as_str(), false),
+            };
+
+            // `===` compares strings, so anything goes.
+            let version = match version.parse::<Version>() {
+                Ok(version) => version,
+                Err(_) if *op == "===" => Version { epoch: 0, release: vec![], pre: None, post: None, dev: None, local: vec![] },
+                Err(e) => return Err(e),
+            };
+            if *op == "~=" && version.release.len() < 2 {
+                return Err(format!("'{}' needs at least two release numbers", s));
+            }
+
+            Ok(Specifier { op: op.to_string(), version, wildcard, text })
+        }
+    }
+
+fn same_release(a: &Version, b: &Version) -> bool {
+    a.epoch == b.epoch && a.trimmed_release() == b.trimmed_release()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(s: &str) -> Version {
+        s.parse().unwrap()
+    }
+
+    fn allows(specifiers: &str, version: &str) -> bool {
+        specifiers.parse::<Specifiers>().unwrap().contains(&v(version))
+    }
+
+    #[test]
+    fn parses_and_normalizes() {
+        assert_eq!(v("1.0-RC.1").to_string(), "1.0rc1");
+        assert_eq!(v("v2.0.post").to_string(), "2.0.post0");
+        assert_eq!(v("1!1.2-3").to_string(), "1!1.2.post3");
+        assert_eq!(v("1.0.dev").to_string(), "1.0.dev0");
+        assert_eq!(v("1.0+Ubuntu-1").to_string(), "1.0+ubuntu.1");
+        assert!("1.0-foo".parse::<Version>().is_err());
+        assert!("".parse::<Version>().is_err());
+    }
+
+    #[test]
+    fn orders_like_pep_440() {
+        let ordered = ["1.0.dev0", "1.0a1.dev1", "1.0a1", "1.0b2", "1.0rc1", "1.0", "1.0+local", "1.0.post1.dev0", "1.0.post1", "1.1", "1!0.5"];
+        for pair in ordered.windows(2) {
+            assert!(v(pair[0]) < v(pair[1]), "{} < {}", pair[0], pair[1]);
+        }
+        assert_eq!(v("1.0"), v("1.0.0"));
+    }
+
+    #[test]
+    fn specifiers() {
+        assert!(allows(">=1.2,<2", "1.9.9"));
+        assert!(!allows(">=1.2,<2", "2.0"));
+        assert!(allows("~=1.4.2", "1.4.9") && !allows("~=1.4.2", "1.5"));
+        assert!(allows("==1.4.*", "1.4.7") && !allows("!=1.4.*", "1.4.7"));
+        assert!(allows("==1.0", "1.0+local") && !allows("==1.0+other", "1.0+local"));
+        assert!(!allows(">1.7", "1.7.post1") && allows(">1.7", "1.7.1"));
+        assert!(!allows("<2,>=1.0a1", "2.0rc1") && allows("<2,>=1.0a1", "1.9rc1"));
+        assert!(!allows(">=1", "2.0b1") && allows(">=2.0b1", "2.0b2"));
+        assert!(allows("", "3.0"));
+    }
+}
@@ -0,0 +1,281 @@
+use std::collections::BTreeMap;
+
+use super::{dists::normalize, pep440::Specifiers};
+
+/// The outcome of merging detected requirements into an existing requirements.txt.
+#[derive(Debug, PartialEq)]
+pub struct Merge {
+    /// The new content of the file.
+    pub text: String,
+    /// Lines appended for newly detected distributions.
+    pub added: Vec<String>,
+    /// Existing requirement lines whose distribution is no longer imported. They are kept.
+    pub unused: Vec<String>,
+}
+
+/// Merges `detected` (distribution, installed version) into the requirements.txt `existing`.
+/// Every existing line is kept as it is, with its pins, extras, markers, options and comments.
+/// Distributions that aren't listed yet are appended, pinned to their installed version.
+pub fn merge(existing: &str, detected: &[(String, Option<String>)]) -> Merge {
+    let listed = existing.lines()
+        .filter_map(|line| requirement_name(line).map(|name| (normalize(&name), line.trim().to_string())))
+        .collect::<Vec<(String, String)>>();
+
+    let mut new = BTreeMap::new();
+    for (name, version) in detected {
+        let key = normalize(name);
+        if !listed.iter().any(|(listed, _)| *listed == key) {
+            let line = match version {
+                Some(version) => format!("{}=={}", name, version),
+                None => name.to_owned(),
+            };
+            new.entry(key).or_insert(line);
+        }
+    }
+    let added = new.into_values().collect::<Vec<String>>();
+
+    let unused = listed.into_iter()
+        .filter(|(listed, _)| !detected.iter().any(|(name, _)| normalize(name) == *listed))
+        .map(|(_, line)| line)
+        .collect();
+
+    let mut text = existing.to_string();
+    if !text.is_empty() && !text.ends_with('\n') && !added.is_empty() {
+        text.push('\n');
+    }
+    for line in &added {
+        text.push_str(line);
+        text.push('\n');
+    }
+
+    Merge { text, added, unused }
+}
+
+/// A unified diff of requirements.txt from `old` to `new`, with up to three lines of context around
+/// each change. The lines in `flagged` are kept in both but shown as changes, marked with `?`.
+pub fn diff(old: &str, new: &str, flagged: &[String]) -> Vec<String> {
+    const CONTEXT: usize = 3;
+    let old = old.lines().collect::<Vec<&str>>();
+    let new = new.lines().collect::<Vec<&str>>();
+
+    // The longest common subsequence of lines, from the end, to walk the two files from the start.
+    let mut common = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] { common[i + 1][j + 1] + 1 } else { common[i + 1][j].max(common[i][j + 1]) };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut lines = vec![];
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            let mark = if flagged.iter().any(|line| line == old[i].trim()) { '?' } else { ' ' };
+            lines.push((mark, old[i]));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || common[i + 1][j] >= common[i][j + 1]) {
+            lines.push(('-', old[i]));
+            i += 1;
+        } else {
+            lines.push(('+', new[j]));
+            j += 1;
+        }
+    }
+
+    let changes = lines.iter().enumerate().filter(|(_, (mark, _))| *mark != ' ').map(|(at, _)| at).collect::<Vec<usize>>();
+    let mut hunks: Vec<(usize, usize)> = vec![];
+    for at in changes {
+        let (start, end) = (at.saturating_sub(CONTEXT), (at + CONTEXT + 1).min(lines.len()));
+        match hunks.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    let mut out = vec![];
+    for (start, end) in hunks {
+        let before = &lines[..start];
+        let hunk = &lines[start..end];
+        let old_start = before.iter().filter(|(mark, _)| *mark != '+').count();
+        let new_start = before.iter().filter(|(mark, _)| *mark != '-').count();
+        let old_len = hunk.iter().filter(|(mark, _)| *mark != '+').count();
+        let new_len = hunk.iter().filter(|(mark, _)| *mark != '-').count();
+        let first = |at: usize, len: usize| if len == 0 { at } else { at + 1 };
+
+        out.push(format!("@@ -{},{} +{},{} @@", first(old_start, old_len), old_len, first(new_start, new_len), new_len));
+        out.extend(hunk.iter().map(|(mark, line)| format!("{}{}", mark, line)));
+    }
+    out
+}
+
+/// The distribution a requirements.txt line is about, if it is a requirement. Editable
+/// and URL lines only name one through `#egg=`, other options and comments name none.
+pub fn requirement_name(line: &str) -> Option<String> {
+    let line = line.trim();
+    if let Some(egg) = line.split_once("#egg=").map(|(_, egg)| egg) {
+        return name_prefix(egg);
+    }
+
+    let line = match line.find(" #") {
+        Some(comment) => &line[..comment],
+        None => line,
+    };
+    if line.is_empty() || line.starts_with('#') || line.starts_with('-') || line.contains("://") && !line.contains(" @ ") {
+        return None;
+    }
+    name_prefix(line)
+}
+
+/// The name and version specifiers of a requirements.txt line: `flask[async]>=2,<3 ; python_version > "3.8"`
+/// gives `("flask", ">=2,<3")`. Options, URL and path requirements give None.
+pub fn requirement_specifiers(line: &str) -> Option<(String, String)> {
+    let name = requirement_name(line)?;
+    let line = line.trim();
+    if line.starts_with('-') || line.contains("://") {
+        return None;
+    }
+
+    // A name from `#egg=` isn't where the line starts, and such a line has no specifiers.
+    let rest = line.strip_prefix(name.as_str())?;
+    let rest = rest.split(" #").next().unwrap_or(rest);
+    let rest = rest.split(';').next().unwrap_or(rest).trim_start();
+    let rest = match rest.strip_prefix('[') {
+        Some(extras) => &extras[extras.find(']')? + 1..],
+        None => rest,
+    };
+    Some((name, rest.trim().trim_start_matches('(').trim_end_matches(')').trim().to_string()))
+}
+
+/// Repins the requirements pinned with `==` whose normalized name is in `versions` to the version given
+/// there. Returns the new text and the lines that changed, as (old, new). Everything else is kept as it is.
+pub fn upgrade_pins(existing: &str, versions: &BTreeMap<String, String>) -> (String, Vec<(String, String)>) {
+    let mut text = String::new();
+    let mut changed = vec![];
+
+    for line in existing.split_inclusive('\n') {
+        let repinned = requirement_specifiers(line).and_then(|(name, specifiers)| {
+            let version = versions.get(&normalize(&name))?;
+            let specifiers = specifiers.parse::<Specifiers>().ok()?;
+            let pin = specifiers.pin().filter(|pin| pin != version)?;
+
+            let start = line.find("==")? + 2;
+            let start = start + line[start..].find(pin)?;
+            Some(format!("{}{}{}", &line[..start], version, &line[start + pin.len()..]))
+        });
+
+        match repinned {
+            Some(repinned) => {
+                changed.push((line.trim().to_string(), repinned.trim().to_string()));
+                text.push_str(&repinned);
+            },
+            None => text.push_str(line),
+        }
+    }
+    (text, changed)
+}
+
+/// The requirements among `pip install` arguments, leaving out options and the values they take.
+pub fn install_requirements(args: &[String]) -> Vec<String> {
+    const TAKE_VALUE: [&str; 22] = [
+        "-r", "--requirement", "-c", "--constraint", "-e", "--editable", "-t", "--target", "-i", "--index-url", "--extra-index-url",
+        "-f", "--find-links", "--prefix", "--root", "--src", "--upgrade-strategy", "--platform", "--python-version", "--no-binary", "--only-binary", "--config-settings",
+    ];
+
+    let mut requirements = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if TAKE_VALUE.contains(&arg.as_str()) {
+            args.next();
+        } else if !arg.starts_with('-') {
+            requirements.push(arg.to_owned());
+        }
+    }
+    requirements
+}
+
+fn name_prefix(s: &str) -> Option<String> {
+    let name = s.split(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))).next()?;
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detected(names: &[(&str, Option<&str>)]) -> Vec<(String, Option<String>)> {
+        names.iter().map(|(name, version)| (name.to_string(), version.map(String::from))).collect()
+    }
+
+    #[test]
+    fn keeps_existing_lines_and_appends_new_ones() {
+        let existing = "# web\nuvicorn[standard]>=0.20  # server\nflask==2.0.1\n-e git+https://example.com/lib.git#egg=my_lib\npywin32; sys_platform == 'win32'\n";
+        let merge = merge(existing, &detected(&[("Flask", Some("2.3.0")), ("uvicorn", None), ("my-lib", None), ("pywin32", None), ("requests", Some("2.31.0")), ("PyYAML", None)]));
+
+        assert_eq!(merge.text, format!("{}PyYAML\nrequests==2.31.0\n", existing));
+        assert_eq!(merge.added, ["PyYAML", "requests==2.31.0"]);
+        assert!(merge.unused.is_empty());
+    }
+
+    #[test]
+    fn flags_requirements_that_are_no_longer_imported() {
+        let merge = merge("numpy\ngunicorn==20.1\n-r dev.txt\n--index-url https://example.com\n", &detected(&[("numpy", None)]));
+        assert_eq!(merge.unused, ["gunicorn==20.1"]);
+        assert_eq!(merge.text, "numpy\ngunicorn==20.1\n-r dev.txt\n--index-url https://example.com\n");
+    }
+
+    #[test]
+    fn adds_a_newline_before_appending_to_an_unterminated_file() {
+        assert_eq!(merge("numpy", &detected(&[("numpy", None), ("scipy", None)])).text, "numpy\nscipy\n");
+    }
+
+    #[test]
+    fn specifiers_of_a_line() {
+        assert_eq!(requirement_specifiers("flask[async] >=2,<3 ; python_version > '3.8'  # web"), Some((String::from("flask"), String::from(">=2,<3"))));
+        assert_eq!(requirement_specifiers("numpy (==1.26.0)"), Some((String::from("numpy"), String::from("==1.26.0"))));
+        assert_eq!(requirement_specifiers("requests"), Some((String::from("requests"), String::new())));
+        assert_eq!(requirement_specifiers("-e ./lib#egg=mylib"), None);
+        assert_eq!(requirement_specifiers("./vendor/pkg#egg=foo"), None);
+        assert_eq!(requirement_specifiers("./äpkg#egg=foo"), None);
+        assert_eq!(requirement_specifiers("git+https://example.com/lib.git#egg=lib"), None);
+    }
+
+    #[test]
+    fn upgrades_only_exact_pins() {
+        let versions = BTreeMap::from([("flask".to_string(), "3.0.0".to_string()), ("numpy".to_string(), "2.0".to_string()), ("pyyaml".to_string(), "6.0.1".to_string())]);
+        let (text, changed) = upgrade_pins("Flask == 2.0.1  # web\r\nnumpy>=1.20\nPyYAML==6.0.1\n", &versions);
+        assert_eq!(text, "Flask == 3.0.0  # web\r\nnumpy>=1.20\nPyYAML==6.0.1\n");
+        assert_eq!(changed, [(String::from("Flask == 2.0.1  # web"), String::from("Flask == 3.0.0  # web"))]);
+    }
+
+    #[test]
+    fn install_requirements_skip_options_and_their_values() {
+        let args = ["-U", "pytest>=7", "-i", "https://example.com/simple", "--index-url=https://example.com", "black", "-r", "dev.txt"].map(String::from);
+        assert_eq!(install_requirements(&args), ["pytest>=7", "black"]);
+    }
+
+    #[test]
+    fn diff_shows_additions_with_context_and_flags_unused_lines() {
+        let old = "# web\nflask==2.0.1\nuvicorn\nclick\njinja2\nitsdangerous\nwerkzeug\n";
+        let new = format!("{}requests==2.31.0\n", old);
+        let unused = vec![String::from("flask==2.0.1")];
+
+        assert_eq!(diff(old, &new, &unused), [
+            "@@ -1,7 +1,8 @@", " # web", "?flask==2.0.1", " uvicorn", " click", " jinja2", " itsdangerous", " werkzeug", "+requests==2.31.0",
+        ]);
+        assert_eq!(diff(old, &new, &[]), ["@@ -5,3 +5,4 @@", " jinja2", " itsdangerous", " werkzeug", "+requests==2.31.0"]);
+        assert_eq!(diff("", "flask\n", &[]), ["@@ -0,0 +1,1 @@", "+flask"]);
+        assert!(diff(old, old, &[]).is_empty());
+    }
+
+    #[test]
+    fn diff_splits_distant_changes_into_hunks() {
+        let old = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\n";
+        let new = "a\nB\nc\nd\ne\nf\ng\nh\ni\nj\nk\n";
+
+        assert_eq!(diff(old, new, &[]), [
+            "@@ -1,5 +1,5 @@", " a", "-b", "+B", " c", " d", " e",
+            "@@ -8,3 +8,4 @@", " h", " i", " j", "+k",
+        ]);
+    }
+}
@@ -0,0 +1,64 @@
+/// Top level modules of the python standard library, as of 3.11. Modules that were
+/// added or removed in other versions are in `CHANGED` instead. Sorted, for binary search.
+const STDLIB: &[&str] = &[
+    "__future__", "_abc", "_aix_support", "_ast", "_asyncio", "_bisect", "_blake2", "_bootsubprocess",
+    "_bz2", "_codecs", "_codecs_cn", "_codecs_hk", "_codecs_iso2022", "_codecs_jp", "_codecs_kr",
+    "_codecs_tw", "_collections", "_collections_abc", "_compat_pickle", "_compression", "_contextvars",
+    "_crypt", "_csv", "_ctypes", "_curses", "_curses_panel", "_datetime", "_dbm", "_decimal",
+    "_elementtree", "_frozen_importlib", "_frozen_importlib_external", "_functools", "_gdbm",
+    "_hashlib", "_heapq", "_imp", "_io", "_json", "_locale", "_lsprof", "_lzma", "_markupbase", "_md5",
+    "_msi", "_multibytecodec", "_multiprocessing", "_opcode", "_operator", "_osx_support",
+    "_overlapped", "_pickle", "_posixshmem", "_posixsubprocess", "_py_abc", "_pydecimal", "_pyio",
+    "_queue", "_random", "_scproxy", "_sha1", "_sha256", "_sha3", "_sha512", "_signal",
+    "_sitebuiltins", "_socket", "_sqlite3", "_sre", "_ssl", "_stat", "_statistics", "_string",
+    "_strptime", "_struct", "_symtable", "_thread", "_threading_local", "_tkinter", "_tokenize",
+    "_tracemalloc", "_typing", "_uuid", "_warnings", "_weakref", "_weakrefset", "_winapi", "_zoneinfo",
+    "abc", "antigravity", "argparse", "array", "ast", "asyncio", "atexit", "base64", "bdb", "binascii",
+    "bisect", "builtins", "bz2", "cProfile", "calendar", "cmath", "cmd", "code", "codecs", "codeop",
+    "collections", "colorsys", "compileall", "concurrent", "configparser", "contextlib", "contextvars",
+    "copy", "copyreg", "csv", "ctypes", "curses", "dataclasses", "datetime", "dbm", "decimal",
+    "difflib", "dis", "doctest", "email", "encodings", "ensurepip", "enum", "errno", "faulthandler",
+    "fcntl", "filecmp", "fileinput", "fnmatch", "fractions", "ftplib", "functools", "gc",
+    "genericpath", "getopt", "getpass", "gettext", "glob", "grp", "gzip", "hashlib", "heapq", "hmac",
+    "html", "http", "idlelib", "imaplib", "importlib", "inspect", "io", "ipaddress", "itertools",
+    "json", "keyword", "linecache", "locale", "logging", "lzma", "mailbox", "marshal", "math",
+    "mimetypes", "mmap", "modulefinder", "msvcrt", "multiprocessing", "netrc", "nt", "ntpath",
+    "nturl2path", "numbers", "opcode", "operator", "optparse", "os", "pathlib", "pdb", "pickle",
+    "pickletools", "pkgutil", "platform", "plistlib", "poplib", "posix", "posixpath", "pprint",
+    "profile", "pstats", "pty", "pwd", "py_compile", "pyclbr", "pydoc", "pydoc_data", "pyexpat",
+    "queue", "quopri", "random", "re", "readline", "reprlib", "resource", "rlcompleter", "runpy",
+    "sched", "secrets", "select", "selectors", "shelve", "shlex", "shutil", "signal", "site",
+    "smtplib", "socket", "socketserver", "sqlite3", "sre_compile", "sre_constants", "sre_parse", "ssl",
+    "stat", "statistics", "string", "stringprep", "struct", "subprocess", "symtable", "sys",
+    "sysconfig", "syslog", "tabnanny", "tarfile", "tempfile", "termios", "textwrap", "this",
+    "threading", "time", "timeit", "tkinter", "token", "tokenize", "trace", "traceback", "tracemalloc",
+    "tty", "turtle", "turtledemo", "types", "typing", "unicodedata", "unittest", "urllib", "uuid",
+    "venv", "warnings", "wave", "weakref", "webbrowser", "winreg", "winsound", "wsgiref", "xml",
+    "xmlrpc", "zipapp", "zipfile", "zipimport", "zlib"
+];
+
+/// (module, first minor version that has it, first minor version that doesn't), for python 3.
+const CHANGED: &[(&str, u32, u32)] = &[
+    ("_dummy_thread", 0, 9), ("dummy_threading", 0, 9),
+    ("formatter", 0, 10), ("parser", 0, 10), ("symbol", 0, 10),
+    ("binhex", 0, 11),
+    ("asynchat", 0, 12), ("asyncore", 0, 12), ("distutils", 0, 12), ("imp", 0, 12), ("smtpd", 0, 12),
+    ("aifc", 0, 13), ("audioop", 0, 13), ("cgi", 0, 13), ("cgitb", 0, 13), ("chunk", 0, 13), ("crypt", 0, 13),
+    ("imghdr", 0, 13), ("lib2to3", 0, 13), ("mailcap", 0, 13), ("msilib", 0, 13), ("nis", 0, 13), ("nntplib", 0, 13),
+    ("ossaudiodev", 0, 13), ("pipes", 0, 13), ("sndhdr", 0, 13), ("spwd", 0, 13), ("sunau", 0, 13), ("telnetlib", 0, 13),
+    ("uu", 0, 13), ("xdrlib", 0, 13),
+    ("graphlib", 9, u32::MAX), ("zoneinfo", 9, u32::MAX),
+    ("tomllib", 11, u32::MAX),
+    ("annotationlib", 14, u32::MAX), ("compression", 14, u32::MAX),
+];
+
+/// The version assumed when the project's python version isn't known.
+pub const LATEST: (u32, u32) = (3, 14);
+
+/// Whether `module` is part of the standard library of python `version` (major, minor).
+pub fn is_stdlib(module: &str, version: (u32, u32)) -> bool {
+    if let Some((_, added, removed)) = CHANGED.iter().find(|(name, _, _)| *name == module) {
+        return (*added..*removed).contains(&version.1);
+    }
+    STDLIB.binary_search(&module).is_ok()
+}
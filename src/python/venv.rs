@@ -0,0 +1,132 @@
+use std::{fs, io, path::{Path, PathBuf}};
+
+/// The venv's site-packages folder, if the venv exists.
+pub fn site_packages(venv: &Path) -> Option<PathBuf> {
+    if cfg!(windows) {
+        let site_packages = venv.join("Lib").join("site-packages");
+        return site_packages.is_dir().then_some(site_packages);
+    }
+
+    fs::read_dir(venv.join("lib")).ok()?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with("python"))
+        .map(|entry| entry.path().join("site-packages"))
+        .find(|site_packages| site_packages.is_dir())
+}
+
+/// The full version of the venv's python, e.g. `3.11.4`, read from its pyvenv.cfg.
+/// virtualenv writes it as `3.11.4.final.0`, which is cut down to the same.
+pub fn python_full_version(venv: &Path) -> Option<String> {
+    let cfg = fs::read_to_string(venv.join("pyvenv.cfg")).ok()?;
+    cfg.lines()
+        .filter_map(|line| line.split_once('='))
+        .find(|(key, _)| matches!(key.trim(), "version" | "version_info"))
+        .map(|(_, version)| version.trim().splitn(4, '.').take(3).collect::<Vec<&str>>().join("."))
+}
+
+/// The (major, minor) part of a python version.
+pub fn minor_version(version: &str) -> Option<(u32, u32)> {
+    let mut parts = version.split('.');
+    Some((parts.next()?.parse().ok()?, parts.next()?.parse().ok()?))
+}
+
+/// The folder of the python the venv was made from, which it keeps using, from its pyvenv.cfg.
+pub fn base_home(venv: &Path) -> Option<PathBuf> {
+    let cfg = fs::read_to_string(venv.join("pyvenv.cfg")).ok()?;
+    cfg.lines()
+        .filter_map(|line| line.split_once('='))
+        .find(|(key, _)| key.trim() == "home")
+        .map(|(_, home)| PathBuf::from(home.trim()))
+}
+
+/// Where the venv was when its scripts were written, going by the python in their shebangs.
+/// Long paths, or paths with spaces, get a `#!/bin/sh` shebang that `exec`s the python instead.
+pub fn original_location(bin: &Path) -> Option<PathBuf> {
+    scripts(bin).into_iter().find_map(|script| {
+        let content = fs::read_to_string(script).ok()?;
+        let mut lines = content.lines();
+        let python = match lines.next()?.strip_prefix("#!")?.trim() {
+            "/bin/sh" => lines.next()?.strip_prefix("'''exec' \"")?.split('"').next()?,
+            shebang => shebang,
+        };
+        let python = Path::new(python);
+        if !python.file_name()?.to_string_lossy().starts_with("python") {
+            return None;
+        }
+        python.parent()?.parent().map(Path::to_owned)
+    })
+}
+
+/// Fixes a venv moved from `old` to `venv`, whose scripts' shebangs, activate scripts and
+/// pyvenv.cfg still name `old`. Returns how many files were rewritten. Binary files, like
+/// the script launchers on Windows, are left alone.
+pub fn relocate(venv: &Path, bin: &Path, old: &Path) -> io::Result<usize> {
+    let (old, new) = (old.to_string_lossy(), venv.to_string_lossy());
+    let mut fixed = 0;
+    for file in scripts(bin).into_iter().chain([venv.join("pyvenv.cfg")]) {
+        let content = match fs::read_to_string(&file) {
+            Ok(content) => content,
+            Err(_) => continue,
+        };
+        if content.contains(old.as_ref()) {
+            fs::write(&file, content.replace(old.as_ref(), &new))?;
+            fixed += 1;
+        }
+    }
+    Ok(fixed)
+}
+
+/// The regular files in `bin`, leaving out the links to the base python.
+fn scripts(bin: &Path) -> Vec<PathBuf> {
+    let mut scripts = fs::read_dir(bin).into_iter().flatten()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_file()))
+        .map(|entry| entry.path())
+        .collect::<Vec<PathBuf>>();
+    scripts.sort();
+    scripts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A venv made at `venv`: a script with a plain shebang, one with the `/bin/sh` shebang
+    /// used for long paths, an activate script and a pyvenv.cfg.
+    fn fake_venv(venv: &Path) {
+        let bin = venv.join("bin");
+        fs::create_dir_all(&bin).unwrap();
+        fs::write(bin.join("activate"), format!("VIRTUAL_ENV='{}'\nexport VIRTUAL_ENV\n", venv.display())).unwrap();
+        fs::write(bin.join("pip"), format!("#!{}/bin/python3\nimport sys\n", venv.display())).unwrap();
+        fs::write(bin.join("uvicorn"), format!("#!/bin/sh\n'''exec' \"{}/bin/python3\" \"$0\" \"$@\"\n' '''\nimport sys\n", venv.display())).unwrap();
+        fs::write(venv.join("pyvenv.cfg"), format!("home = /usr/bin\nversion = 3.12.1\ncommand = /usr/bin/python3 -m venv {}\n", venv.display())).unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("/usr/bin/python3", bin.join("python3")).unwrap();
+    }
+
+    #[test]
+    fn moved_venv_is_relocated() {
+        let dir = std::env::temp_dir().join(format!("pie-relocate-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let (old, new) = (dir.join("old project/venv"), dir.join("new/venv"));
+        fake_venv(&old);
+        assert_eq!(original_location(&old.join("bin")), Some(old.to_owned()));
+
+        fs::create_dir_all(new.parent().unwrap()).unwrap();
+        fs::rename(&old, &new).unwrap();
+        let bin = new.join("bin");
+        assert_eq!(original_location(&bin), Some(old.to_owned()));
+        assert_eq!(base_home(&new), Some(PathBuf::from("/usr/bin")));
+
+        assert_eq!(relocate(&new, &bin, &old).unwrap(), 4);
+        assert_eq!(original_location(&bin), Some(new.to_owned()));
+        for file in [bin.join("activate"), bin.join("pip"), bin.join("uvicorn"), new.join("pyvenv.cfg")] {
+            let content = fs::read_to_string(&file).unwrap();
+            assert!(content.contains(&new.display().to_string()) && !content.contains(&old.display().to_string()), "{}", file.display());
+        }
+        assert_eq!(fs::read_to_string(bin.join("pip")).unwrap(), format!("#!{}/bin/python3\nimport sys\n", new.display()));
+        assert_eq!(relocate(&new, &bin, &old).unwrap(), 0);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,238 @@
+use std::{cmp::Ordering, fmt, str::FromStr};
+
+use clap::ArgEnum;
+
+/// A strict SemVer 2.0.0 version (https://semver.org). Versions compare by precedence,
+/// so two that only differ in build metadata are equal.
+#[derive(Debug, Clone)]
+pub struct Version {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+    pub pre: Vec<String>,
+    pub build: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, ArgEnum)]
+pub enum BumpLevel {
+    Major,
+    Minor,
+    Patch,
+    Pre,
+}
+
+    impl Version {
+        /// Returns the next version for `level`. Bumping a pre-release to the
+        /// level it is a pre-release of just drops the pre-release part,
+        /// e.g. `2.0.0-rc.1` bumped by major is `2.0.0`.
+        pub fn bump(&self, level: BumpLevel, pre_id: Option<&str>) -> Version {
+            let is_pre = !self.pre.is_empty();
+            let mut next = Version { pre: vec![], build: vec![], ..self.clone() };
+
+            match level {
+                BumpLevel::Major => {
+                    if !(is_pre && self.minor == 0 && self.patch == 0) {
+                        next.major += 1;
+                        next.minor = 0;
+                        next.patch = 0;
+                    }
+                },
+                BumpLevel::Minor => {
+                    if !(is_pre && self.patch == 0) {
+                        next.minor += 1;
+                        next.patch = 0;
+                    }
+                },
+                BumpLevel::Patch => {
+                    if !is_pre {
+                        next.patch += 1;
+                    }
+                },
+                BumpLevel::Pre => {
+                    let current_id = self.pre.first().filter(|id| id.parse::<u64>().is_err());
+                    let id = pre_id.map(str::to_string).or_else(|| current_id.cloned()).unwrap_or_else(|| String::from("rc"));
+
+                    if !is_pre {
+                        next.patch += 1;
+                        next.pre = vec![id, String::from("1")];
+                    } else if current_id == Some(&id) {
+                        next.pre = self.pre.clone();
+                        match next.pre.last().and_then(|last| last.parse::<u64>().ok()) {
+                            Some(n) => *next.pre.last_mut().unwrap() = (n + 1).to_string(),
+                            None => next.pre.push(String::from("1")),
+                        }
+                    } else {
+                        next.pre = vec![id, String::from("1")];
+                    }
+                }
+            }
+
+            next
+        }
+    }
+
+    impl FromStr for Version {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Version, String> {
+            let (rest, build) = match s.split_once('+') {
+                Some((rest, build)) => (rest, parse_identifiers(build, "build metadata", false)?),
+                None => (s, vec![]),
+            };
+            let (core, pre) = match rest.split_once('-') {
+                Some((core, pre)) => (core, parse_identifiers(pre, "pre-release", true)?),
+                None => (rest, vec![]),
+            };
+
+            let parts = core.split('.').collect::<Vec<&str>>();
+            if parts.len() != 3 {
+                return Err(format!("'{}' must have exactly three parts, MAJOR.MINOR.PATCH", core));
+            }
+
+            Ok(Version {
+                major: parse_numeric(parts[0], "major")?,
+                minor: parse_numeric(parts[1], "minor")?,
+                patch: parse_numeric(parts[2], "patch")?,
+                pre,
+                build,
+            })
+        }
+    }
+
+    impl fmt::Display for Version {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
+            if !self.pre.is_empty() {
+                write!(f, "-{}", self.pre.join("."))?;
+            }
+            if !self.build.is_empty() {
+                write!(f, "+{}", self.build.join("."))?;
+            }
+            Ok(())
+        }
+    }
+
+    impl PartialEq for Version {
+        fn eq(&self, other: &Version) -> bool {
+            self.cmp(other) == Ordering::Equal
+        }
+    }
+
+    impl Eq for Version {}
+
+    impl PartialOrd for Version {
+        fn partial_cmp(&self, other: &Version) -> Option<Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+
+    // Precedence as defined by the spec: build metadata is ignored and a
+    // pre-release sorts before its release.
+    impl Ord for Version {
+        fn cmp(&self, other: &Version) -> Ordering {
+            (self.major, self.minor, self.patch).cmp(&(other.major, other.minor, other.patch))
+                .then_with(|| match (self.pre.is_empty(), other.pre.is_empty()) {
+                    (true, true) => Ordering::Equal,
+                    (true, false) => Ordering::Greater,
+                    (false, true) => Ordering::Less,
+                    (false, false) => cmp_pre(&self.pre, &other.pre),
+                })
+        }
+    }
+
+fn cmp_pre(a: &[String], b: &[String]) -> Ordering {
+    for (x, y) in a.iter().zip(b) {
+        let ord = match (x.parse::<u64>(), y.parse::<u64>()) {
+            (Ok(x), Ok(y)) => x.cmp(&y),
+            (Ok(_), Err(_)) => Ordering::Less,
+            (Err(_), Ok(_)) => Ordering::Greater,
+            (Err(_), Err(_)) => x.cmp(y),
+        };
+        if ord != Ordering::Equal {
+            return ord;
+        }
+    }
+    a.len().cmp(&b.len())
+}
+
+fn parse_numeric(part: &str, name: &str) -> Result<u64, String> {
+    if part.is_empty() || !part.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(format!("{} version '{}' is not a number", name, part));
+    }
+    if part.len() > 1 && part.starts_with('0') {
+        return Err(format!("{} version '{}' has a leading zero", name, part));
+    }
+    part.parse().map_err(|_| format!("{} version '{}' is too large", name, part))
+}
+
+fn parse_identifiers(s: &str, name: &str, numeric_leading_zero: bool) -> Result<Vec<String>, String> {
+    s.split('.').map(|id| {
+        if id.is_empty() {
+            return Err(format!("{} '{}' contains an empty identifier", name, s));
+        }
+        if !id.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-') {
+            return Err(format!("{} identifier '{}' may only contain [0-9A-Za-z-]", name, id));
+        }
+        if numeric_leading_zero && id.len() > 1 && id.starts_with('0') && id.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(format!("{} identifier '{}' has a leading zero", name, id));
+        }
+        Ok(id.to_string())
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(s: &str) -> Version {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parse_valid() {
+        let version = v("1.2.3-rc.1+build.5");
+        assert_eq!((version.major, version.minor, version.patch), (1, 2, 3));
+        assert_eq!((version.pre, version.build), (vec![String::from("rc"), String::from("1")], vec![String::from("build"), String::from("5")]));
+        assert_eq!(v("0.0.0+001").build, ["001"]);
+        assert_eq!(v("1.0.0-x-y.0a").to_string(), "1.0.0-x-y.0a");
+    }
+
+    #[test]
+    fn parse_invalid() {
+        for invalid in ["01.0.0", "1.00.0", "1.0.0-01", "1.0", "1.0.0.0", "1.0.0-", "1.0.0-a..b", "1.0.0+", "1.0.0+a..b", "1.0.0-a_b", "v1.0.0", ""] {
+            assert!(invalid.parse::<Version>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn precedence_ignores_build() {
+        assert!(v("1.0.0-alpha") < v("1.0.0-alpha.1") && v("1.0.0-alpha.1") < v("1.0.0-alpha.beta") && v("1.0.0-rc.1") < v("1.0.0"));
+        assert!(v("1.0.0-2") < v("1.0.0-11") && v("1.0.0-11") < v("1.0.0-a"));
+        assert_eq!(v("1.0.0+a"), v("1.0.0+b"));
+        assert_eq!(v("1.0.0+a").cmp(&v("1.0.0+b")), Ordering::Equal);
+    }
+
+    #[test]
+    fn bump_release() {
+        assert_eq!(v("1.2.3+b").bump(BumpLevel::Major, None).to_string(), "2.0.0");
+        assert_eq!(v("1.2.3").bump(BumpLevel::Minor, None).to_string(), "1.3.0");
+        assert_eq!(v("1.2.3").bump(BumpLevel::Patch, None).to_string(), "1.2.4");
+    }
+
+    #[test]
+    fn bump_pre_release_to_its_release() {
+        assert_eq!(v("1.0.0-rc.1").bump(BumpLevel::Patch, None).to_string(), "1.0.0");
+        assert_eq!(v("1.1.0-rc.1").bump(BumpLevel::Minor, None).to_string(), "1.1.0");
+        assert_eq!(v("2.0.0-rc.1").bump(BumpLevel::Major, None).to_string(), "2.0.0");
+        assert_eq!(v("1.1.1-rc.1").bump(BumpLevel::Minor, None).to_string(), "1.2.0");
+    }
+
+    #[test]
+    fn bump_pre() {
+        assert_eq!(v("1.0.0").bump(BumpLevel::Pre, None).to_string(), "1.0.1-rc.1");
+        assert_eq!(v("1.0.0").bump(BumpLevel::Pre, Some("beta")).to_string(), "1.0.1-beta.1");
+        assert_eq!(v("1.0.1-beta.1").bump(BumpLevel::Pre, None).to_string(), "1.0.1-beta.2");
+        assert_eq!(v("1.0.1-beta.2").bump(BumpLevel::Pre, Some("rc")).to_string(), "1.0.1-rc.1");
+        assert_eq!(v("1.0.1-beta").bump(BumpLevel::Pre, None).to_string(), "1.0.1-beta.1");
+    }
+}